@@ -0,0 +1,15 @@
+CREATE SCHEMA IF NOT EXISTS data_scraper;
+
+CREATE TABLE data_scraper.outbox
+(
+    id            BIGSERIAL PRIMARY KEY,
+    topic         TEXT        NOT NULL,
+    payload       BYTEA       NOT NULL,
+    attributes    JSONB       NOT NULL DEFAULT '{}',
+    created_at    TIMESTAMPTZ NOT NULL DEFAULT now(),
+    attempts      INTEGER     NOT NULL DEFAULT 0,
+    last_error    TEXT,
+    dispatched_at TIMESTAMPTZ
+);
+
+CREATE INDEX outbox_pending_idx ON data_scraper.outbox (id) WHERE dispatched_at IS NULL;
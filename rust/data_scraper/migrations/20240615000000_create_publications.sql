@@ -0,0 +1,20 @@
+CREATE TABLE data_scraper.publications
+(
+    mods_id                TEXT PRIMARY KEY,
+    series_id              TEXT,
+    title                  TEXT        NOT NULL,
+    reference_period_start DATE,
+    reference_period_end   DATE,
+    published_on           DATE,
+    edition                TEXT,
+    notes                  TEXT[]      NOT NULL DEFAULT '{}',
+    license                TEXT,
+    fetched_at             TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- No foreign key, rows imported before publications were recorded have no publication.
+-- The tables are created outside this crate, the migration fails if one is missing.
+ALTER TABLE original_data.uebernachtungen_nach_herkunftsland
+    ADD COLUMN IF NOT EXISTS publication_id TEXT;
+ALTER TABLE original_data.uebernachtungen_pro_land
+    ADD COLUMN IF NOT EXISTS publication_id TEXT;
@@ -0,0 +1,16 @@
+CREATE TABLE data_scraper.quarantine
+(
+    id             BIGSERIAL PRIMARY KEY,
+    run_id         UUID        NOT NULL,
+    series_id      TEXT        NOT NULL,
+    mods_id        TEXT,
+    source_url     TEXT        NOT NULL,
+    source_md5     TEXT        NOT NULL,
+    sheet          TEXT,
+    error          TEXT        NOT NULL,
+    details        JSONB,
+    quarantined_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    released_at    TIMESTAMPTZ
+);
+
+CREATE INDEX quarantine_source_idx ON data_scraper.quarantine (source_md5) WHERE released_at IS NULL;
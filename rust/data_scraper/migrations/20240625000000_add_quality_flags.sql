@@ -0,0 +1,13 @@
+-- Destatis quality flags of the numbers next to them, e.g. 'not_available' or 'preliminary'.
+ALTER TABLE original_data.uebernachtungen_nach_herkunftsland
+    ADD COLUMN IF NOT EXISTS ankuenfte_anzahl_flag                                     TEXT,
+    ADD COLUMN IF NOT EXISTS ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent_flag TEXT,
+    ADD COLUMN IF NOT EXISTS uebernachtungen_anzahl_flag                               TEXT,
+    ADD COLUMN IF NOT EXISTS uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent_flag TEXT,
+    ADD COLUMN IF NOT EXISTS durchsch_aufenthaltsdauer_tage_flag                       TEXT;
+ALTER TABLE original_data.uebernachtungen_pro_land
+    ADD COLUMN IF NOT EXISTS ankuenfte_anzahl_flag                                     TEXT,
+    ADD COLUMN IF NOT EXISTS ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent_flag TEXT,
+    ADD COLUMN IF NOT EXISTS uebernachtungen_anzahl_flag                               TEXT,
+    ADD COLUMN IF NOT EXISTS uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent_flag TEXT,
+    ADD COLUMN IF NOT EXISTS durchsch_aufenthaltsdauer_tage_flag                       TEXT;
@@ -0,0 +1,9 @@
+ALTER TABLE data_scraper.quarantine
+    ADD COLUMN kind             TEXT    NOT NULL DEFAULT 'workbook',
+    ADD COLUMN period_year      INTEGER,
+    ADD COLUMN period_month     INTEGER,
+    ADD COLUMN row_index        INTEGER,
+    ADD COLUMN raw_row          JSONB,
+    ADD COLUMN attempts         INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN last_retry_error TEXT,
+    ADD COLUMN retried_at       TIMESTAMPTZ;
@@ -0,0 +1,17 @@
+CREATE TABLE data_scraper.quality_violations
+(
+    id           BIGSERIAL PRIMARY KEY,
+    run_id       UUID             NOT NULL,
+    table_name   TEXT             NOT NULL,
+    period_year  INT              NOT NULL,
+    period_month INT              NOT NULL,
+    rule         TEXT             NOT NULL,
+    subject      TEXT             NOT NULL,
+    field        TEXT             NOT NULL,
+    expected     DOUBLE PRECISION,
+    actual       DOUBLE PRECISION NOT NULL,
+    detected_at  TIMESTAMPTZ      NOT NULL DEFAULT now()
+);
+
+CREATE INDEX quality_violations_month_idx ON data_scraper.quality_violations (table_name, period_year, period_month);
+CREATE INDEX quality_violations_run_idx ON data_scraper.quality_violations (run_id);
@@ -0,0 +1,12 @@
+-- Changes to the previous year recomputed from the stored counts, next to the published ones.
+-- The abgleich is 'match', 'revised' or 'filled', see src/veraenderung.rs.
+ALTER TABLE original_data.uebernachtungen_nach_herkunftsland
+    ADD COLUMN IF NOT EXISTS ankuenfte_veraenderung_berechnet_prozent       DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS ankuenfte_veraenderung_abgleich                TEXT,
+    ADD COLUMN IF NOT EXISTS uebernachtungen_veraenderung_berechnet_prozent DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS uebernachtungen_veraenderung_abgleich          TEXT;
+ALTER TABLE original_data.uebernachtungen_pro_land
+    ADD COLUMN IF NOT EXISTS ankuenfte_veraenderung_berechnet_prozent       DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS ankuenfte_veraenderung_abgleich                TEXT,
+    ADD COLUMN IF NOT EXISTS uebernachtungen_veraenderung_berechnet_prozent DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS uebernachtungen_veraenderung_abgleich          TEXT;
@@ -0,0 +1,18 @@
+-- Several series are imported into the same tables, each month is stored per series.
+-- Everything stored before is from the Monatsbericht Tourismus.
+ALTER TABLE original_data.uebernachtungen_nach_herkunftsland
+    ADD COLUMN IF NOT EXISTS series_id TEXT NOT NULL DEFAULT 'DESerie_mods_00007671';
+ALTER TABLE original_data.uebernachtungen_nach_herkunftsland
+    ALTER COLUMN series_id DROP DEFAULT;
+ALTER TABLE original_data.uebernachtungen_pro_land
+    ADD COLUMN IF NOT EXISTS series_id TEXT NOT NULL DEFAULT 'DESerie_mods_00007671';
+ALTER TABLE original_data.uebernachtungen_pro_land
+    ALTER COLUMN series_id DROP DEFAULT;
+
+ALTER TABLE data_scraper.quality_violations
+    ADD COLUMN series_id TEXT NOT NULL DEFAULT 'DESerie_mods_00007671';
+ALTER TABLE data_scraper.quality_violations
+    ALTER COLUMN series_id DROP DEFAULT;
+
+DROP INDEX data_scraper.quality_violations_month_idx;
+CREATE INDEX quality_violations_month_idx ON data_scraper.quality_violations (series_id, table_name, period_year, period_month);
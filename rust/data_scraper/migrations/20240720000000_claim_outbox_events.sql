@@ -0,0 +1,4 @@
+-- Events are claimed before they are published, so no transaction stays open while the
+-- notifier is called. A claim that isn't released, e.g. after a crash, expires.
+ALTER TABLE data_scraper.outbox
+    ADD COLUMN claimed_until TIMESTAMPTZ;
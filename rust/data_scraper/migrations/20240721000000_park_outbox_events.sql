@@ -0,0 +1,4 @@
+-- Events that can't be delivered, either after too many attempts or because of an error
+-- that won't go away, are parked so they don't hold up the events after them.
+ALTER TABLE data_scraper.outbox
+    ADD COLUMN parked_at TIMESTAMPTZ;
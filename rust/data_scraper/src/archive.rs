@@ -0,0 +1,292 @@
+use std::io::{Seek, Write};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use log::{debug, info};
+use md5::{Digest, Md5};
+use object_store::aws::AmazonS3Builder;
+use object_store::local::LocalFileSystem;
+use object_store::path::Path;
+use object_store::{ObjectStore, WriteMultipart};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+
+use crate::events::YearMonth;
+use crate::settings::ArchiveSettings;
+
+/// Size of the parts files are uploaded in, S3 requires at least 5 MiB.
+const UPLOAD_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+const UPLOAD_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactKind {
+    Workbook,
+    Page,
+}
+
+impl ArtifactKind {
+    fn prefix(&self) -> &'static str {
+        match self {
+            ArtifactKind::Workbook => "workbooks",
+            ArtifactKind::Page => "pages",
+        }
+    }
+}
+
+/// What an artifact was downloaded for, as far as the downloader knows.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ArtifactOrigin {
+    pub series_id: Option<String>,
+    pub mods_id: Option<String>,
+    pub period: Option<YearMonth>,
+}
+
+/// Stored next to every artifact as `<md5>.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArtifactMetadata {
+    pub kind: ArtifactKind,
+    pub url: String,
+    pub md5: String,
+    pub size: u64,
+    pub content_type: Option<String>,
+    pub fetched_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub origin: ArtifactOrigin,
+}
+
+/// Archive of the original files everything in the database was parsed from, so a
+/// parser fix can be applied to old data with `reprocess`.
+///
+/// Artifacts are addressed by their MD5, archiving the same content twice is a no-op.
+#[derive(Clone)]
+pub struct ArtifactStore {
+    store: Option<Arc<dyn ObjectStore>>,
+}
+
+impl ArtifactStore {
+    pub fn new(settings: &ArchiveSettings) -> Result<Self, anyhow::Error> {
+        let store: Option<Arc<dyn ObjectStore>> = match settings {
+            ArchiveSettings::Local { directory } => {
+                std::fs::create_dir_all(directory).map_err(|e| {
+                    anyhow::anyhow!("Failed to create archive directory {}: {}", directory, e)
+                })?;
+                Some(Arc::new(LocalFileSystem::new_with_prefix(directory)?))
+            }
+            ArchiveSettings::S3 {
+                bucket,
+                endpoint,
+                region,
+                access_key_id,
+                secret_access_key,
+                allow_http,
+            } => {
+                let mut builder = AmazonS3Builder::from_env()
+                    .with_bucket_name(bucket)
+                    .with_region(region)
+                    .with_allow_http(*allow_http);
+                if let Some(endpoint) = endpoint {
+                    builder = builder.with_endpoint(endpoint);
+                }
+                if let Some(access_key_id) = access_key_id {
+                    builder = builder.with_access_key_id(access_key_id);
+                }
+                if let Some(secret_access_key) = secret_access_key {
+                    builder = builder.with_secret_access_key(secret_access_key);
+                }
+                Some(Arc::new(builder.build().map_err(|e| {
+                    anyhow::anyhow!("Failed to configure S3 archive: {}", e)
+                })?))
+            }
+            ArchiveSettings::None => None,
+        };
+        Ok(Self { store })
+    }
+
+    pub async fn put(
+        &self,
+        metadata: &ArtifactMetadata,
+        content: Bytes,
+    ) -> Result<(), anyhow::Error> {
+        let Some((store, content_path)) = self.missing_content_path(metadata).await else {
+            return Ok(());
+        };
+
+        store
+            .put(&content_path, content.into())
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to archive {}: {}", content_path, e))?;
+        self.put_metadata(store, metadata).await
+    }
+
+    /// Like `put`, but uploads the file in parts instead of reading it into memory.
+    pub async fn put_file(
+        &self,
+        metadata: &ArtifactMetadata,
+        file: &std::path::Path,
+    ) -> Result<(), anyhow::Error> {
+        let Some((store, content_path)) = self.missing_content_path(metadata).await else {
+            return Ok(());
+        };
+
+        let mut file = tokio::fs::File::open(file).await?;
+        let upload = store
+            .put_multipart(&content_path)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to archive {}: {}", content_path, e))?;
+        let mut writer = WriteMultipart::new(upload);
+        let mut buffer = vec![0; UPLOAD_CHUNK_SIZE];
+        loop {
+            let read = file.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            writer.wait_for_capacity(UPLOAD_CONCURRENCY).await?;
+            writer.write(&buffer[..read]);
+        }
+        writer
+            .finish()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to archive {}: {}", content_path, e))?;
+        self.put_metadata(store, metadata).await
+    }
+
+    /// The store and the path the content belongs at, unless it is already archived.
+    async fn missing_content_path(
+        &self,
+        metadata: &ArtifactMetadata,
+    ) -> Option<(&Arc<dyn ObjectStore>, Path)> {
+        let store = self.store.as_ref()?;
+        let content_path = content_path(metadata.kind, &metadata.md5);
+        if store.head(&content_path).await.is_ok() {
+            debug!("{} is already archived", content_path);
+            return None;
+        }
+        Some((store, content_path))
+    }
+
+    /// The metadata goes last, list() only sees artifacts that are complete.
+    async fn put_metadata(
+        &self,
+        store: &Arc<dyn ObjectStore>,
+        metadata: &ArtifactMetadata,
+    ) -> Result<(), anyhow::Error> {
+        let metadata_path = metadata_path(metadata.kind, &metadata.md5);
+        store
+            .put(&metadata_path, serde_json::to_vec(metadata)?.into())
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to archive {}: {}", metadata_path, e))?;
+        info!(
+            "Archived {} as {}",
+            metadata.url,
+            content_path(metadata.kind, &metadata.md5)
+        );
+        Ok(())
+    }
+
+    /// Metadata of every archived artifact of the kind, oldest first.
+    pub async fn list(&self, kind: ArtifactKind) -> Result<Vec<ArtifactMetadata>, anyhow::Error> {
+        let store = self.require_store()?;
+        let objects = store
+            .list(Some(&Path::from(kind.prefix())))
+            .try_collect::<Vec<_>>()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list archive: {}", e))?;
+
+        let mut artifacts = Vec::new();
+        for object in objects {
+            if object.location.extension() != Some("json") {
+                continue;
+            }
+            let content = store.get(&object.location).await?.bytes().await?;
+            let metadata = serde_json::from_slice::<ArtifactMetadata>(&content).map_err(|e| {
+                anyhow::anyhow!("Invalid artifact metadata {}: {}", object.location, e)
+            })?;
+            artifacts.push(metadata);
+        }
+        artifacts.sort_by_key(|x| x.fetched_at);
+        Ok(artifacts)
+    }
+
+    /// Metadata of the artifact with the given MD5, if it is archived. Nothing is
+    /// archived while the archive is disabled.
+    pub async fn find(
+        &self,
+        kind: ArtifactKind,
+        md5: &str,
+    ) -> Result<Option<ArtifactMetadata>, anyhow::Error> {
+        let Some(store) = &self.store else {
+            return Ok(None);
+        };
+        let path = metadata_path(kind, md5);
+        let content = match store.get(&path).await {
+            Ok(x) => x.bytes().await?,
+            Err(object_store::Error::NotFound { .. }) => return Ok(None),
+            Err(e) => return Err(anyhow::anyhow!("Failed to read {}: {}", path, e)),
+        };
+        Ok(Some(serde_json::from_slice(&content).map_err(|e| {
+            anyhow::anyhow!("Invalid artifact metadata {}: {}", path, e)
+        })?))
+    }
+
+    /// Copies the archived content into a tempfile and checks its MD5.
+    pub async fn get_to_tempfile(
+        &self,
+        metadata: &ArtifactMetadata,
+    ) -> Result<std::fs::File, anyhow::Error> {
+        let mut file = tempfile::tempfile()
+            .map_err(|e| anyhow::anyhow!("Failed to create tempfile: {}", e))?;
+        self.get_to_file(metadata, &mut file).await?;
+
+        let mut hasher = Md5::new();
+        file.rewind()?;
+        std::io::copy(&mut file, &mut hasher)?;
+        let md5 = format!("{:x}", hasher.finalize());
+        if md5 != metadata.md5 {
+            return Err(anyhow::anyhow!(
+                "Archived {} has md5 {}, expected {}",
+                metadata.url,
+                md5,
+                metadata.md5
+            ));
+        }
+        file.rewind()?;
+        Ok(file)
+    }
+
+    /// Streams the archived content into the file.
+    pub async fn get_to_file(
+        &self,
+        metadata: &ArtifactMetadata,
+        file: &mut std::fs::File,
+    ) -> Result<(), anyhow::Error> {
+        let path = content_path(metadata.kind, &metadata.md5);
+        let mut stream = self
+            .require_store()?
+            .get(&path)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to read {} from the archive: {}", path, e))?
+            .into_stream();
+        while let Some(chunk) = stream.try_next().await? {
+            file.write_all(&chunk)
+                .map_err(|e| anyhow::anyhow!("Failed to copy bytes: {}", e))?;
+        }
+        Ok(())
+    }
+
+    fn require_store(&self) -> Result<&Arc<dyn ObjectStore>, anyhow::Error> {
+        self.store
+            .as_ref()
+            .ok_or(anyhow::anyhow!("The archive is disabled in the settings"))
+    }
+}
+
+fn content_path(kind: ArtifactKind, md5: &str) -> Path {
+    Path::from(format!("{}/{}", kind.prefix(), md5))
+}
+
+fn metadata_path(kind: ArtifactKind, md5: &str) -> Path {
+    Path::from(format!("{}/{}.json", kind.prefix(), md5))
+}
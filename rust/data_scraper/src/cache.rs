@@ -0,0 +1,349 @@
+use std::fs::File;
+use std::io::{Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use log::{debug, info, warn};
+use md5::{Digest, Md5};
+use reqwest::header::{CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::archive::{ArtifactKind, ArtifactMetadata, ArtifactOrigin, ArtifactStore};
+use crate::http::{HttpClient, HttpStatusError};
+use crate::metrics::METRICS;
+use crate::mycore::Derivative;
+use crate::settings::CacheSettings;
+
+const DERIVATIVES_DIRECTORY: &str = "derivatives";
+const PAGES_DIRECTORY: &str = "pages";
+/// Entries are written here first. Eviction doesn't look into it, so it can't remove
+/// a download that is still in flight.
+const TMP_DIRECTORY: &str = "tmp";
+
+/// Local cache for everything we download.
+///
+/// Workbooks are stored under their MD5, which the API tells us before we download
+/// them, so a known workbook is never fetched twice. Pages are stored together with
+/// their `ETag` and `Last-Modified` and revalidated with a conditional request.
+/// Everything that actually came over the network is also put into the archive, and so
+/// are cached workbooks that aren't archived yet.
+#[derive(Clone)]
+pub struct DownloadCache {
+    directory: PathBuf,
+    max_bytes: u64,
+    max_age: Duration,
+    archive: ArtifactStore,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedPage {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    body: String,
+}
+
+impl DownloadCache {
+    pub fn new(settings: &CacheSettings, archive: ArtifactStore) -> Result<Self, anyhow::Error> {
+        let directory = PathBuf::from(&settings.directory);
+        for subdirectory in [DERIVATIVES_DIRECTORY, PAGES_DIRECTORY, TMP_DIRECTORY] {
+            std::fs::create_dir_all(directory.join(subdirectory)).map_err(|e| {
+                anyhow::anyhow!(
+                    "Failed to create cache directory {}: {}",
+                    directory.display(),
+                    e
+                )
+            })?;
+        }
+
+        Ok(Self {
+            directory,
+            max_bytes: settings.max_size_mb * 1024 * 1024,
+            max_age: Duration::from_secs(settings.max_age_days * 24 * 60 * 60),
+            archive,
+        })
+    }
+
+    /// Returns the workbook from the cache, or downloads it and checks it against the
+    /// MD5 and size the API reported.
+    pub async fn derivative(
+        &self,
+        client: &HttpClient,
+        derivative: &Derivative,
+        origin: ArtifactOrigin,
+    ) -> Result<File, anyhow::Error> {
+        let md5 = derivative.md5.to_lowercase();
+        if md5.len() != 32 || !md5.chars().all(|x| x.is_ascii_hexdigit()) {
+            return Err(anyhow::anyhow!(
+                "Derivative {} has an invalid md5: {}",
+                derivative.name,
+                derivative.md5
+            ));
+        }
+
+        let path = self.directory.join(DERIVATIVES_DIRECTORY).join(&md5);
+        if let Some(file) = open_if_size_matches(&path, derivative.size as u64) {
+            debug!("Using cached {} ({})", derivative.name, md5);
+            METRICS
+                .cache_lookups
+                .with_label_values(&["derivative", "hit"])
+                .inc();
+            // It may have been cached before the archive was enabled.
+            self.archive
+                .put_file(
+                    &workbook_metadata(derivative, &md5, derivative.size as u64, origin),
+                    &path,
+                )
+                .await?;
+            return Ok(file);
+        }
+        METRICS
+            .cache_lookups
+            .with_label_values(&["derivative", "miss"])
+            .inc();
+
+        let mut file = self.tempfile()?;
+        let size = client
+            .download(
+                &derivative.href,
+                file.as_file_mut(),
+                Some(derivative.size as u64),
+            )
+            .await
+            .map_err(|e| {
+                anyhow::anyhow!("Failed to download derivative: {}, {}", &derivative.href, e)
+            })?;
+        METRICS
+            .downloaded_bytes
+            .with_label_values(&[&derivative.name])
+            .inc_by(size);
+
+        if size != derivative.size as u64 {
+            return Err(anyhow::anyhow!(
+                "Derivative {} has {} bytes, expected {}",
+                derivative.name,
+                size,
+                derivative.size
+            ));
+        }
+        let mut hasher = Md5::new();
+        file.rewind()?;
+        std::io::copy(file.as_file_mut(), &mut hasher)
+            .map_err(|e| anyhow::anyhow!("Failed to hash {}: {}", derivative.name, e))?;
+        let actual_md5 = format!("{:x}", hasher.finalize());
+        if actual_md5 != md5 {
+            return Err(anyhow::anyhow!(
+                "Derivative {} has md5 {}, expected {}",
+                derivative.name,
+                actual_md5,
+                md5
+            ));
+        }
+
+        self.archive
+            .put_file(
+                &workbook_metadata(derivative, &md5, size, origin),
+                file.path(),
+            )
+            .await?;
+
+        file.persist(&path)
+            .map_err(|e| anyhow::anyhow!("Failed to persist cache entry: {}", e))?;
+        info!("Cached {} as {}", derivative.name, md5);
+        let file =
+            File::open(&path).map_err(|e| anyhow::anyhow!("Failed to open cached file: {}", e))?;
+        self.evict(Some(&path));
+        Ok(file)
+    }
+
+    /// Fetches an HTML or JSON page, revalidating a cached copy with `If-None-Match`
+    /// and `If-Modified-Since` if we have one.
+    pub async fn page(&self, client: &HttpClient, url: &str) -> Result<String, anyhow::Error> {
+        let path = self
+            .directory
+            .join(PAGES_DIRECTORY)
+            .join(format!("{:x}.json", Md5::digest(url.as_bytes())));
+        let cached = std::fs::read(&path)
+            .ok()
+            .and_then(|x| serde_json::from_slice::<CachedPage>(&x).ok())
+            .filter(|x| x.url == url);
+
+        let mut request = client.get(url);
+        if let Some(cached) = &cached {
+            if let Some(etag) = &cached.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let response = client
+            .send(request)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to fetch {}: {}", url, e))?;
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some(cached) = cached {
+                debug!("{} not modified, using the cached copy", url);
+                METRICS
+                    .cache_lookups
+                    .with_label_values(&["page", "hit"])
+                    .inc();
+                touch(&path);
+                return Ok(cached.body);
+            }
+        }
+        METRICS
+            .cache_lookups
+            .with_label_values(&["page", "miss"])
+            .inc();
+
+        let response = HttpStatusError::check(response)?;
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|x| x.to_str().ok())
+                .map(|x| x.to_string())
+        };
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+        let content_type = header(CONTENT_TYPE);
+        let body = response
+            .text()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", url, e))?;
+
+        self.archive
+            .put(
+                &ArtifactMetadata {
+                    kind: ArtifactKind::Page,
+                    url: url.to_string(),
+                    md5: format!("{:x}", Md5::digest(body.as_bytes())),
+                    size: body.len() as u64,
+                    content_type,
+                    fetched_at: chrono::Utc::now(),
+                    origin: ArtifactOrigin::default(),
+                },
+                body.clone().into(),
+            )
+            .await?;
+
+        // Without a validator there is nothing to revalidate against next time.
+        if etag.is_some() || last_modified.is_some() {
+            let page = CachedPage {
+                url: url.to_string(),
+                etag,
+                last_modified,
+                body,
+            };
+            self.store(&path, &serde_json::to_vec(&page)?)?;
+            return Ok(page.body);
+        }
+        Ok(body)
+    }
+
+    /// Writes through a temporary file, so a crash never leaves a truncated entry behind.
+    fn store(&self, path: &Path, content: &[u8]) -> Result<(), anyhow::Error> {
+        let mut file = self.tempfile()?;
+        file.write_all(content)
+            .map_err(|e| anyhow::anyhow!("Failed to write cache entry: {}", e))?;
+        file.persist(path)
+            .map_err(|e| anyhow::anyhow!("Failed to persist cache entry: {}", e))?;
+        Ok(())
+    }
+
+    /// In the cache directory, so it can be persisted into it with a rename.
+    fn tempfile(&self) -> Result<tempfile::NamedTempFile, anyhow::Error> {
+        tempfile::NamedTempFile::new_in(self.directory.join(TMP_DIRECTORY))
+            .map_err(|e| anyhow::anyhow!("Failed to create tempfile: {}", e))
+    }
+
+    /// Removes entries unused for longer than `max_age_days`, then the least recently
+    /// used ones until the cache fits into `max_size_mb`. `keep` is never removed, e.g.
+    /// the entry that was just written.
+    pub fn evict(&self, keep: Option<&Path>) {
+        let mut entries = Vec::new();
+        let mut kept = 0;
+        for subdirectory in [DERIVATIVES_DIRECTORY, PAGES_DIRECTORY] {
+            let Ok(read_dir) = std::fs::read_dir(self.directory.join(subdirectory)) else {
+                continue;
+            };
+            for entry in read_dir.flatten() {
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                if !metadata.is_file() {
+                    continue;
+                }
+                if keep == Some(entry.path().as_path()) {
+                    kept += metadata.len();
+                    continue;
+                }
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                entries.push((modified, metadata.len(), entry.path()));
+            }
+        }
+
+        entries.sort();
+        let now = SystemTime::now();
+        let mut total = kept + entries.iter().map(|(_, size, _)| size).sum::<u64>();
+        for (modified, size, path) in entries {
+            let expired = now.duration_since(modified).is_ok_and(|x| x > self.max_age);
+            if !expired && total <= self.max_bytes {
+                break;
+            }
+            match std::fs::remove_file(&path) {
+                Ok(()) => {
+                    debug!("Evicted {} from the cache", path.display());
+                    total -= size;
+                }
+                Err(e) => warn!("Failed to evict {}: {}", path.display(), e),
+            }
+        }
+    }
+}
+
+fn workbook_metadata(
+    derivative: &Derivative,
+    md5: &str,
+    size: u64,
+    origin: ArtifactOrigin,
+) -> ArtifactMetadata {
+    ArtifactMetadata {
+        kind: ArtifactKind::Workbook,
+        url: derivative.href.clone(),
+        md5: md5.to_string(),
+        size,
+        content_type: Some(derivative.content_type.clone()),
+        fetched_at: chrono::Utc::now(),
+        origin,
+    }
+}
+
+fn open_if_size_matches(path: &Path, size: u64) -> Option<File> {
+    let file = File::open(path).ok()?;
+    if file.metadata().ok()?.len() != size {
+        warn!(
+            "Cached {} has the wrong size, discarding it",
+            path.display()
+        );
+        let _ = std::fs::remove_file(path);
+        return None;
+    }
+    touch(path);
+    Some(file)
+}
+
+/// Marks an entry as recently used, eviction goes by modification time.
+fn touch(path: &Path) {
+    if let Err(e) = File::options()
+        .append(true)
+        .open(path)
+        .and_then(|x| x.set_modified(SystemTime::now()))
+    {
+        debug!("Failed to touch {}: {}", path.display(), e);
+    }
+}
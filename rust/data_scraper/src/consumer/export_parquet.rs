@@ -0,0 +1,195 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, Float64Array, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema};
+use async_trait::async_trait;
+use log::info;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use crate::consumer::EventHandler;
+use crate::context::Context;
+use crate::events::NewDataEvent;
+
+/// Tables that are exported, each into `<table>.parquet`.
+const EXPORTED_TABLES: [&str; 2] = [
+    "uebernachtungen_nach_herkunftsland",
+    "uebernachtungen_pro_land",
+];
+
+/// Rewrites the Parquet export of every table the event added rows to. The tables are
+/// small, so they are exported as a whole, replacing the previous file atomically.
+pub struct ExportParquetHandler {
+    directory: PathBuf,
+}
+
+impl ExportParquetHandler {
+    pub fn new(directory: &str) -> Self {
+        Self {
+            directory: PathBuf::from(directory),
+        }
+    }
+
+    async fn export(&self, context: &Context, table: &str) -> Result<usize, anyhow::Error> {
+        let database = &context.database_client;
+        let batch = match table {
+            "uebernachtungen_nach_herkunftsland" => {
+                let rows = sqlx::query_file!(
+                    "src/queries/select_uebernachtungen_nach_herkunftsland_export.sql"
+                )
+                .fetch_all(database)
+                .await?;
+                Columns::default()
+                    .text("series_id", rows.iter().map(|x| Some(x.series_id.as_str())))
+                    .text(
+                        "herkunftsregion",
+                        rows.iter().map(|x| x.herkunftsregion.as_deref()),
+                    )
+                    .int("jahr", rows.iter().map(|x| x.jahr))
+                    .text("monat", rows.iter().map(|x| x.monat.as_deref()))
+                    .int("ankuenfte_anzahl", rows.iter().map(|x| x.ankuenfte_anzahl))
+                    .float(
+                        "ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent",
+                        rows.iter()
+                            .map(|x| x.ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent),
+                    )
+                    .int(
+                        "uebernachtungen_anzahl",
+                        rows.iter().map(|x| x.uebernachtungen_anzahl),
+                    )
+                    .float(
+                        "uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent",
+                        rows.iter()
+                            .map(|x| x.uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent),
+                    )
+                    .float(
+                        "durchsch_aufenthaltsdauer_tage",
+                        rows.iter().map(|x| x.durchsch_aufenthaltsdauer_tage),
+                    )
+                    .text(
+                        "publication_id",
+                        rows.iter().map(|x| x.publication_id.as_deref()),
+                    )
+                    .batch()?
+            }
+            "uebernachtungen_pro_land" => {
+                let rows =
+                    sqlx::query_file!("src/queries/select_uebernachtungen_pro_land_export.sql")
+                        .fetch_all(database)
+                        .await?;
+                Columns::default()
+                    .text("series_id", rows.iter().map(|x| Some(x.series_id.as_str())))
+                    .text("land", rows.iter().map(|x| x.land.as_deref()))
+                    .text("wohnsitz", rows.iter().map(|x| x.wohnsitz.as_deref()))
+                    .int("jahr", rows.iter().map(|x| x.jahr))
+                    .text("monat", rows.iter().map(|x| x.monat.as_deref()))
+                    .int("ankuenfte_anzahl", rows.iter().map(|x| x.ankuenfte_anzahl))
+                    .float(
+                        "ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent",
+                        rows.iter()
+                            .map(|x| x.ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent),
+                    )
+                    .int(
+                        "uebernachtungen_anzahl",
+                        rows.iter().map(|x| x.uebernachtungen_anzahl),
+                    )
+                    .float(
+                        "uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent",
+                        rows.iter()
+                            .map(|x| x.uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent),
+                    )
+                    .float(
+                        "durchsch_aufenthaltsdauer_tage",
+                        rows.iter().map(|x| x.durchsch_aufenthaltsdauer_tage),
+                    )
+                    .text(
+                        "publication_id",
+                        rows.iter().map(|x| x.publication_id.as_deref()),
+                    )
+                    .batch()?
+            }
+            _ => return Err(anyhow::anyhow!("Table {} is not exported", table)),
+        };
+
+        // Written next to the target, so it can be moved into place with a rename.
+        let path = self.directory.join(format!("{}.parquet", table));
+        let mut file = tempfile::NamedTempFile::new_in(&self.directory)
+            .map_err(|e| anyhow::anyhow!("Failed to create tempfile: {}", e))?;
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let mut writer =
+            ArrowWriter::try_new(file.as_file_mut(), batch.schema(), Some(properties))?;
+        writer.write(&batch)?;
+        writer.close()?;
+        file.persist(&path)
+            .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", path.display(), e))?;
+        Ok(batch.num_rows())
+    }
+}
+
+#[async_trait]
+impl EventHandler for ExportParquetHandler {
+    fn name(&self) -> &'static str {
+        "export_parquet"
+    }
+
+    fn accepts(&self, event: &NewDataEvent) -> bool {
+        EXPORTED_TABLES
+            .iter()
+            .any(|x| event.row_counts.contains_key(*x))
+    }
+
+    async fn handle(&self, context: &Context, event: &NewDataEvent) -> Result<(), anyhow::Error> {
+        for table in EXPORTED_TABLES
+            .iter()
+            .filter(|x| event.row_counts.contains_key(**x))
+        {
+            let rows = self.export(context, table).await?;
+            info!(
+                "Exported {} rows of {} after event {}",
+                rows, table, event.event_id
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The columns of a batch, all nullable.
+#[derive(Default)]
+struct Columns {
+    fields: Vec<Field>,
+    arrays: Vec<ArrayRef>,
+}
+
+impl Columns {
+    fn text<'a>(mut self, name: &str, values: impl Iterator<Item = Option<&'a str>>) -> Self {
+        self.fields.push(Field::new(name, DataType::Utf8, true));
+        self.arrays
+            .push(Arc::new(values.collect::<StringArray>()) as ArrayRef);
+        self
+    }
+
+    fn int(mut self, name: &str, values: impl Iterator<Item = Option<i64>>) -> Self {
+        self.fields.push(Field::new(name, DataType::Int64, true));
+        self.arrays
+            .push(Arc::new(values.collect::<Int64Array>()) as ArrayRef);
+        self
+    }
+
+    fn float(mut self, name: &str, values: impl Iterator<Item = Option<f64>>) -> Self {
+        self.fields.push(Field::new(name, DataType::Float64, true));
+        self.arrays
+            .push(Arc::new(values.collect::<Float64Array>()) as ArrayRef);
+        self
+    }
+
+    fn batch(self) -> Result<RecordBatch, anyhow::Error> {
+        Ok(RecordBatch::try_new(
+            Arc::new(Schema::new(self.fields)),
+            self.arrays,
+        )?)
+    }
+}
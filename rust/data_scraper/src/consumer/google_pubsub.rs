@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use google_cloud_pubsub::subscription::SubscriptionConfig;
+use log::{error, info};
+
+use crate::consumer::{Acknowledgement, Consumer};
+use crate::notifier::connect_pub_sub;
+
+pub async fn consume(consumer: Arc<Consumer>) -> Result<(), anyhow::Error> {
+    let settings = &consumer.context.settings;
+    let client = connect_pub_sub(&settings.notifier).await?;
+
+    let topic = client.topic(&settings.consumer.topic);
+    if !topic.exists(None).await? {
+        topic.create(None, None).await?;
+    }
+    let subscription = client.subscription(&settings.consumer.subscription);
+    if !subscription.exists(None).await? {
+        info!(
+            "Creating subscription {} for topic {}",
+            settings.consumer.subscription, settings.consumer.topic
+        );
+        subscription
+            .create(
+                topic.fully_qualified_name(),
+                SubscriptionConfig::default(),
+                None,
+            )
+            .await?;
+    }
+
+    // Pub/Sub only counts delivery attempts for subscriptions with a dead letter
+    // policy, otherwise we count them ourselves.
+    let attempts = Arc::new(Mutex::new(HashMap::<String, u32>::new()));
+    let cancellation = consumer.context.cancellation.clone();
+
+    subscription
+        .receive(
+            move |message, _cancel| {
+                let consumer = consumer.clone();
+                let attempts = attempts.clone();
+                async move {
+                    let message_id = message.message.message_id.clone();
+                    let delivery_attempt = match message.delivery_attempt() {
+                        Some(attempt) => attempt as u32,
+                        None => {
+                            let mut attempts = attempts.lock().unwrap();
+                            let attempt = attempts.entry(message_id.clone()).or_default();
+                            *attempt += 1;
+                            *attempt
+                        }
+                    };
+
+                    let acknowledgement = consumer
+                        .process(
+                            &message.message.data,
+                            &message.message.attributes,
+                            delivery_attempt,
+                        )
+                        .await;
+
+                    let result = match acknowledgement {
+                        Acknowledgement::Ack => {
+                            attempts.lock().unwrap().remove(&message_id);
+                            message.ack().await
+                        }
+                        Acknowledgement::Nack => message.nack().await,
+                    };
+                    if let Err(e) = result {
+                        error!(
+                            "Failed to {:?} message {}: {}",
+                            acknowledgement, message_id, e
+                        );
+                    }
+                }
+            },
+            cancellation,
+            None,
+        )
+        .await?;
+
+    info!("Stopped consuming");
+    Ok(())
+}
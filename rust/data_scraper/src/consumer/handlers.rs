@@ -0,0 +1,83 @@
+use async_trait::async_trait;
+use log::info;
+use sqlx::Executor;
+
+use crate::consumer::export_parquet::ExportParquetHandler;
+use crate::context::Context;
+use crate::events::NewDataEvent;
+use crate::settings::Settings;
+
+#[async_trait]
+pub trait EventHandler: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn accepts(&self, _event: &NewDataEvent) -> bool {
+        true
+    }
+
+    async fn handle(&self, context: &Context, event: &NewDataEvent) -> Result<(), anyhow::Error>;
+}
+
+/// Fails for settings that would leave the consumer without anything to do.
+pub fn handlers_from_settings(
+    settings: &Settings,
+) -> Result<Vec<Box<dyn EventHandler>>, anyhow::Error> {
+    let export_directory = settings.export.directory.as_deref();
+    let settings = &settings.consumer;
+    if settings.handlers.is_empty() {
+        return Err(anyhow::anyhow!("consumer.handlers must not be empty"));
+    }
+    settings
+        .handlers
+        .iter()
+        .map(|name| -> Result<Box<dyn EventHandler>, anyhow::Error> {
+            match name.as_str() {
+                "rebuild_derived_tables" if settings.rebuild_statements.is_empty() => {
+                    Err(anyhow::anyhow!(
+                        "consumer.rebuild_statements must not be empty for the rebuild_derived_tables handler"
+                    ))
+                }
+                "rebuild_derived_tables" => Ok(Box::new(RebuildDerivedTablesHandler {
+                    statements: settings.rebuild_statements.clone(),
+                })),
+                "export_parquet" => match export_directory {
+                    Some(directory) => Ok(Box::new(ExportParquetHandler::new(directory))),
+                    None => Err(anyhow::anyhow!(
+                        "export.directory must be set for the export_parquet handler"
+                    )),
+                },
+                _ => Err(anyhow::anyhow!("Unknown event handler: {}", name)),
+            }
+        })
+        .collect()
+}
+
+/// Runs the configured SQL statements in one transaction, e.g. to refresh the
+/// materialized views the analysis is based on.
+pub struct RebuildDerivedTablesHandler {
+    statements: Vec<String>,
+}
+
+#[async_trait]
+impl EventHandler for RebuildDerivedTablesHandler {
+    fn name(&self) -> &'static str {
+        "rebuild_derived_tables"
+    }
+
+    async fn handle(&self, context: &Context, event: &NewDataEvent) -> Result<(), anyhow::Error> {
+        let mut tx = context.database_client.begin().await?;
+        for statement in &self.statements {
+            tx.execute(statement.as_str()).await.map_err(|e| {
+                anyhow::anyhow!("Failed to execute rebuild statement '{}': {}", statement, e)
+            })?;
+        }
+        tx.commit().await?;
+
+        info!(
+            "Rebuilt derived tables after {} event {}",
+            event.source.as_str(),
+            event.event_id
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use log::{error, info, warn};
+
+pub use handlers::{handlers_from_settings, EventHandler};
+
+use crate::context::Context;
+use crate::events::NewDataEvent;
+use crate::settings::NotifierSettings;
+
+mod export_parquet;
+mod google_pubsub;
+mod handlers;
+mod postgres;
+
+/// What the subscription should do with a message after it was processed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Acknowledgement {
+    Ack,
+    /// Ask for redelivery, the handlers failed but may succeed on a later attempt.
+    Nack,
+}
+
+/// Dispatches incoming events to the registered handlers.
+///
+/// Handlers run again on every redelivery, so they have to be idempotent.
+pub struct Consumer {
+    context: Context,
+    handlers: Vec<Box<dyn EventHandler>>,
+}
+
+/// Listens on the bus configured as notifier until the process is shut down.
+pub async fn run(context: Context) -> Result<(), anyhow::Error> {
+    let handlers = handlers_from_settings(&context.settings)?;
+    info!(
+        "Consuming {} with handlers: {}",
+        context.settings.consumer.topic,
+        handlers
+            .iter()
+            .map(|x| x.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    let consumer = Arc::new(Consumer {
+        context: context.clone(),
+        handlers,
+    });
+
+    match &context.settings.notifier {
+        NotifierSettings::GooglePubSub | NotifierSettings::PubSubEmulator { .. } => {
+            google_pubsub::consume(consumer).await
+        }
+        NotifierSettings::PostgresNotify => postgres::consume(consumer).await,
+        other => Err(anyhow::anyhow!(
+            "Consuming events is not supported with notifier {:?}",
+            other
+        )),
+    }
+}
+
+impl Consumer {
+    pub async fn process(
+        &self,
+        payload: &[u8],
+        attributes: &HashMap<String, String>,
+        delivery_attempt: u32,
+    ) -> Acknowledgement {
+        let event = match NewDataEvent::from_json(payload) {
+            Ok(event) => event,
+            Err(e) => {
+                // Redelivering a message we can't parse won't help.
+                self.dead_letter(payload, attributes, &e).await;
+                return Acknowledgement::Ack;
+            }
+        };
+
+        let mut failure = None;
+        for handler in self.handlers.iter().filter(|x| x.accepts(&event)) {
+            info!(
+                "Running handler {} for event {} (attempt {})",
+                handler.name(),
+                event.event_id,
+                delivery_attempt
+            );
+            if let Err(e) = handler.handle(&self.context, &event).await {
+                error!(
+                    "Handler {} failed for event {}: {}",
+                    handler.name(),
+                    event.event_id,
+                    e
+                );
+                failure = Some(anyhow::anyhow!("Handler {} failed: {}", handler.name(), e));
+                break;
+            }
+        }
+
+        match failure {
+            None => Acknowledgement::Ack,
+            Some(e) if delivery_attempt >= self.context.settings.consumer.max_delivery_attempts => {
+                self.dead_letter(payload, attributes, &e).await;
+                Acknowledgement::Ack
+            }
+            Some(_) => Acknowledgement::Nack,
+        }
+    }
+
+    async fn dead_letter(
+        &self,
+        payload: &[u8],
+        attributes: &HashMap<String, String>,
+        error: &anyhow::Error,
+    ) {
+        let settings = &self.context.settings.consumer;
+        warn!(
+            "Moving message to dead letter topic {}: {}",
+            settings.dead_letter_topic, error
+        );
+
+        let mut attributes = attributes.clone();
+        attributes.insert("original_topic".to_string(), settings.topic.clone());
+        attributes.insert("error".to_string(), error.to_string());
+        if let Err(e) = self
+            .context
+            .notifier
+            .publish(&settings.dead_letter_topic, payload, &attributes)
+            .await
+        {
+            // The message is acknowledged anyway, otherwise it would be redelivered forever.
+            error!("Failed to dead letter message, dropping it: {}", e);
+        }
+    }
+}
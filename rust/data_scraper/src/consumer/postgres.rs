@@ -0,0 +1,51 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{info, warn};
+use sqlx::postgres::PgListener;
+use tokio::select;
+
+use crate::consumer::{Acknowledgement, Consumer};
+
+/// `LISTEN`s on the topic. Notifications can't be redelivered, so failed messages are
+/// retried in place with a growing delay until they are acknowledged or dead lettered.
+pub async fn consume(consumer: Arc<Consumer>) -> Result<(), anyhow::Error> {
+    let context = &consumer.context;
+    let mut listener = PgListener::connect_with(&context.database_client).await?;
+    listener.listen(&context.settings.consumer.topic).await?;
+    info!("Listening on channel {}", context.settings.consumer.topic);
+
+    loop {
+        let notification = select! {
+            _ = context.cancellation.cancelled() => break,
+            notification = listener.recv() => notification?,
+        };
+
+        let attributes = HashMap::new();
+        let mut delivery_attempt = 1;
+        while consumer
+            .process(
+                notification.payload().as_bytes(),
+                &attributes,
+                delivery_attempt,
+            )
+            .await
+            == Acknowledgement::Nack
+        {
+            let delay = Duration::from_secs(2u64.pow(delivery_attempt.min(6)));
+            warn!(
+                "Retrying notification in {:?} (attempt {})",
+                delay, delivery_attempt
+            );
+            select! {
+                _ = context.cancellation.cancelled() => return Ok(()),
+                _ = tokio::time::sleep(delay) => {}
+            }
+            delivery_attempt += 1;
+        }
+    }
+
+    info!("Stopped consuming");
+    Ok(())
+}
@@ -1,10 +1,59 @@
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+
+use std::sync::Arc;
+
+use tokio::select;
+use tokio_util::sync::CancellationToken;
+
+use crate::archive::ArtifactStore;
+use crate::cache::DownloadCache;
+use crate::http::HttpClient;
+use crate::notifier::Notifier;
 use crate::settings::Settings;
 
 #[derive(Clone)]
 #[allow(dead_code)]
 pub struct Context {
     pub settings: Settings,
-    pub http_client: reqwest::Client,
-    pub pubsub_client: google_cloud_pubsub::client::Client,
+    pub http_client: HttpClient,
+    pub cache: DownloadCache,
+    pub archive: ArtifactStore,
+    pub notifier: Arc<dyn Notifier>,
     pub database_client: sqlx::PgPool,
+    /// Cancelled once the process is asked to shut down.
+    pub cancellation: CancellationToken,
+}
+
+/// Error returned by tasks that stopped early because of a shutdown.
+#[derive(Debug)]
+pub struct TaskCancelled;
+
+impl Display for TaskCancelled {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Task was cancelled by shutdown")
+    }
+}
+
+impl std::error::Error for TaskCancelled {}
+
+impl Context {
+    pub fn ensure_not_cancelled(&self) -> Result<(), anyhow::Error> {
+        if self.cancellation.is_cancelled() {
+            return Err(TaskCancelled.into());
+        }
+        Ok(())
+    }
+
+    /// Runs the future until it completes or a shutdown is requested, whichever comes first.
+    pub async fn cancellable<T>(
+        &self,
+        future: impl Future<Output = Result<T, anyhow::Error>>,
+    ) -> Result<T, anyhow::Error> {
+        select! {
+            biased;
+            _ = self.cancellation.cancelled() => Err(TaskCancelled.into()),
+            result = future => result,
+        }
+    }
 }
@@ -0,0 +1,120 @@
+//! Events published through the [`Notifier`](crate::notifier::Notifier).
+//!
+//! Payloads are JSON. Consumers should check `version` and ignore events with a
+//! version they don't know. A sleepover event looks like this:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "event_id": "0b5a2c1e-5d7c-4a8e-9a51-3c1f0e6d2b7a",
+//!   "run_id": "6f0f8f6e-2f9b-4b44-8f7e-0c1a6f2d9e11",
+//!   "source": "sleepover",
+//!   "created_at": "2024-05-14T06:00:12Z",
+//!   "months": [{ "year": 2024, "month": 2 }],
+//!   "laender": ["Bayern", "Berlin"],
+//!   "row_counts": { "uebernachtungen_pro_land": 51 }
+//! }
+//! ```
+//!
+//! The same information is duplicated into message attributes (see
+//! [`NewDataEvent::attributes`]) so subscribers can filter without parsing the payload.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub const NEW_DATA_EVENT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DataSource {
+    Sleepover,
+    Ferien,
+    Weather,
+}
+
+impl DataSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataSource::Sleepover => "sleepover",
+            DataSource::Ferien => "ferien",
+            DataSource::Weather => "weather",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct YearMonth {
+    pub year: i32,
+    pub month: u32,
+}
+
+/// Announces that new rows were written to the database.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NewDataEvent {
+    pub version: u32,
+    pub event_id: Uuid,
+    /// Run of the task that added the data.
+    pub run_id: Uuid,
+    pub source: DataSource,
+    pub created_at: DateTime<Utc>,
+    pub months: BTreeSet<YearMonth>,
+    pub laender: BTreeSet<String>,
+    /// Inserted rows, by table.
+    pub row_counts: BTreeMap<String, u64>,
+}
+
+impl NewDataEvent {
+    pub fn new(source: DataSource, run_id: Uuid) -> Self {
+        Self {
+            version: NEW_DATA_EVENT_VERSION,
+            event_id: Uuid::new_v4(),
+            run_id,
+            source,
+            created_at: Utc::now(),
+            months: BTreeSet::new(),
+            laender: BTreeSet::new(),
+            row_counts: BTreeMap::new(),
+        }
+    }
+
+    pub fn add_rows(&mut self, table: &str, count: u64) {
+        *self.row_counts.entry(table.to_string()).or_default() += count;
+    }
+
+    /// Message attributes for filtering, e.g. `attributes.source = "sleepover"` in Pub/Sub.
+    pub fn attributes(&self) -> HashMap<String, String> {
+        HashMap::from([
+            ("event_type".to_string(), "new_data".to_string()),
+            ("version".to_string(), self.version.to_string()),
+            ("source".to_string(), self.source.as_str().to_string()),
+            ("run_id".to_string(), self.run_id.to_string()),
+            (
+                "months".to_string(),
+                self.months
+                    .iter()
+                    .map(|x| format!("{:04}-{:02}", x.year, x.month))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+        ])
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, anyhow::Error> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    pub fn from_json(payload: &[u8]) -> Result<Self, anyhow::Error> {
+        let event: NewDataEvent = serde_json::from_slice(payload)
+            .map_err(|e| anyhow::anyhow!("Failed to parse new data event: {}", e))?;
+        if event.version != NEW_DATA_EVENT_VERSION {
+            return Err(anyhow::anyhow!(
+                "Unsupported new data event version: {}",
+                event.version
+            ));
+        }
+        Ok(event)
+    }
+}
@@ -0,0 +1,298 @@
+use std::fmt::{Display, Formatter};
+
+use calamine::Data;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+/// Why a Destatis number is missing, or how far it can be trusted. The symbols are the
+/// ones from the "Zeichenerklärung" of the publications.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QualityFlag {
+    /// "-", nothing there, the value is exactly zero.
+    Zero,
+    /// ".", not available or kept secret.
+    NotAvailable,
+    /// "x", not meaningful.
+    NotMeaningful,
+    /// "/", too uncertain to publish.
+    Uncertain,
+    /// "...", not yet available.
+    NotYetAvailable,
+    /// "p", preliminary.
+    Preliminary,
+    /// "r", revised.
+    Revised,
+}
+
+impl QualityFlag {
+    /// Stored in the `*_flag` column next to the number.
+    pub fn code(&self) -> &'static str {
+        match self {
+            QualityFlag::Zero => "zero",
+            QualityFlag::NotAvailable => "not_available",
+            QualityFlag::NotMeaningful => "not_meaningful",
+            QualityFlag::Uncertain => "uncertain",
+            QualityFlag::NotYetAvailable => "not_yet_available",
+            QualityFlag::Preliminary => "preliminary",
+            QualityFlag::Revised => "revised",
+        }
+    }
+
+    /// A placeholder that stands in for the whole cell.
+    fn placeholder(symbol: &str) -> Option<Self> {
+        Some(match symbol {
+            "-" | "–" => QualityFlag::Zero,
+            "." => QualityFlag::NotAvailable,
+            "x" | "X" => QualityFlag::NotMeaningful,
+            "/" => QualityFlag::Uncertain,
+            "..." | "…" => QualityFlag::NotYetAvailable,
+            _ => return None,
+        })
+    }
+
+    /// A suffix that qualifies the number in front of it.
+    fn suffix(symbol: char) -> Option<Self> {
+        match symbol {
+            'p' => Some(QualityFlag::Preliminary),
+            'r' => Some(QualityFlag::Revised),
+            _ => None,
+        }
+    }
+}
+
+impl Display for QualityFlag {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+/// How far a count may be off a whole number, cells hold floats.
+const WHOLE_NUMBER_TOLERANCE: f64 = 1e-6;
+
+/// The default is zero.
+pub trait DestatisNumber: Copy + Default {
+    fn from_f64(value: f64) -> Result<Self, String>;
+}
+
+/// Counts are published as whole numbers.
+impl DestatisNumber for i64 {
+    fn from_f64(value: f64) -> Result<Self, String> {
+        let rounded = value.round();
+        // i64::MAX as f64 is 2^63, which is already out of range.
+        if !rounded.is_finite() || rounded < i64::MIN as f64 || rounded >= i64::MAX as f64 {
+            return Err(format!("{} is out of range for a count", value));
+        }
+        if (value - rounded).abs() > WHOLE_NUMBER_TOLERANCE {
+            return Err(format!("{} is not a whole number", value));
+        }
+        Ok(rounded as i64)
+    }
+}
+
+impl DestatisNumber for f64 {
+    fn from_f64(value: f64) -> Result<Self, String> {
+        Ok(value)
+    }
+}
+
+/// A number from a Destatis table together with its quality flag. An empty cell has
+/// neither.
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq)]
+pub struct DestatisValue<T> {
+    pub value: Option<T>,
+    pub flag: Option<QualityFlag>,
+}
+
+impl<T: DestatisNumber> DestatisValue<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            value: Some(value),
+            flag: None,
+        }
+    }
+
+    pub fn flag_code(&self) -> Option<&'static str> {
+        self.flag.map(|x| x.code())
+    }
+
+    /// Reads "1234", "12,5", "1 234 p" and the placeholders.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Ok(Self::default());
+        }
+        if let Some(flag) = QualityFlag::placeholder(text) {
+            return Ok(Self {
+                value: (flag == QualityFlag::Zero).then(T::default),
+                flag: Some(flag),
+            });
+        }
+
+        let (number, flag) = match text.chars().last().and_then(QualityFlag::suffix) {
+            Some(flag) => (&text[..text.len() - 1], Some(flag)),
+            None => (text, None),
+        };
+        let value = number
+            .replace([' ', '\u{a0}'], "")
+            .replace(',', ".")
+            .parse::<f64>()
+            .map_err(|_| format!("{:?} is neither a number nor a Destatis symbol", text))?;
+        Ok(Self {
+            value: Some(T::from_f64(value)?),
+            flag,
+        })
+    }
+
+    pub fn from_cell(cell: &Data) -> Result<Self, String> {
+        match cell {
+            Data::Empty => Ok(Self::default()),
+            Data::Int(x) => T::from_f64(*x as f64).map(Self::new),
+            Data::Float(x) => T::from_f64(*x).map(Self::new),
+            Data::String(x) => Self::parse(x),
+            x => Err(format!("Unexpected cell {:?}", x)),
+        }
+    }
+}
+
+impl<'de, T: DestatisNumber> Deserialize<'de> for DestatisValue<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::Null => Ok(Self::default()),
+            Value::Number(x) => x
+                .as_f64()
+                .ok_or_else(|| "number out of range".to_string())
+                .and_then(T::from_f64)
+                .map(Self::new)
+                .map_err(de::Error::custom),
+            Value::String(x) => Self::parse(&x).map_err(de::Error::custom),
+            _ => Err(de::Error::custom("wrong type")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flagged<T: DestatisNumber>(value: Option<T>, flag: QualityFlag) -> DestatisValue<T> {
+        DestatisValue {
+            value,
+            flag: Some(flag),
+        }
+    }
+
+    #[test]
+    fn parses_placeholders() {
+        assert_eq!(
+            DestatisValue::<i64>::parse("-"),
+            Ok(flagged(Some(0), QualityFlag::Zero))
+        );
+        assert_eq!(
+            DestatisValue::<i64>::parse("."),
+            Ok(flagged(None, QualityFlag::NotAvailable))
+        );
+        assert_eq!(
+            DestatisValue::<i64>::parse("x"),
+            Ok(flagged(None, QualityFlag::NotMeaningful))
+        );
+        assert_eq!(
+            DestatisValue::<f64>::parse("/"),
+            Ok(flagged(None, QualityFlag::Uncertain))
+        );
+        assert_eq!(
+            DestatisValue::<f64>::parse("..."),
+            Ok(flagged(None, QualityFlag::NotYetAvailable))
+        );
+        assert_eq!(
+            DestatisValue::<f64>::parse(" … "),
+            Ok(flagged(None, QualityFlag::NotYetAvailable))
+        );
+    }
+
+    #[test]
+    fn parses_numbers_with_suffix() {
+        assert_eq!(
+            DestatisValue::<i64>::parse("1 234 p"),
+            Ok(flagged(Some(1234), QualityFlag::Preliminary))
+        );
+        assert_eq!(
+            DestatisValue::<f64>::parse("12,5r"),
+            Ok(flagged(Some(12.5), QualityFlag::Revised))
+        );
+        assert_eq!(
+            DestatisValue::<i64>::parse("1\u{a0}234"),
+            Ok(DestatisValue::new(1234))
+        );
+    }
+
+    #[test]
+    fn parses_negative_numbers() {
+        assert_eq!(
+            DestatisValue::<f64>::parse("-3,2"),
+            Ok(DestatisValue::new(-3.2))
+        );
+        assert_eq!(
+            DestatisValue::<f64>::parse("-0,4 p"),
+            Ok(flagged(Some(-0.4), QualityFlag::Preliminary))
+        );
+    }
+
+    #[test]
+    fn empty_text_has_neither_value_nor_flag() {
+        assert_eq!(
+            DestatisValue::<i64>::parse("  "),
+            Ok(DestatisValue::default())
+        );
+    }
+
+    #[test]
+    fn rejects_a_bare_suffix_and_other_text() {
+        assert!(DestatisValue::<i64>::parse("p").is_err());
+        assert!(DestatisValue::<i64>::parse("r").is_err());
+        assert!(DestatisValue::<f64>::parse("abc").is_err());
+    }
+
+    #[test]
+    fn reads_cells() {
+        assert_eq!(
+            DestatisValue::<i64>::from_cell(&Data::Float(42.0)),
+            Ok(DestatisValue::new(42))
+        );
+        assert_eq!(
+            DestatisValue::<i64>::from_cell(&Data::String(".".to_string())),
+            Ok(flagged(None, QualityFlag::NotAvailable))
+        );
+        assert_eq!(
+            DestatisValue::<f64>::from_cell(&Data::Empty),
+            Ok(DestatisValue::default())
+        );
+        assert!(DestatisValue::<f64>::from_cell(&Data::Bool(true)).is_err());
+    }
+
+    #[test]
+    fn counts_are_rounded_whole_numbers() {
+        assert_eq!(
+            DestatisValue::<i64>::from_cell(&Data::Float(1234.0000001)),
+            Ok(DestatisValue::new(1234))
+        );
+        assert_eq!(
+            DestatisValue::<i64>::from_cell(&Data::Float(1233.9999999)),
+            Ok(DestatisValue::new(1234))
+        );
+        assert!(DestatisValue::<i64>::from_cell(&Data::Float(1234.6)).is_err());
+        assert!(DestatisValue::<i64>::parse("1234,6").is_err());
+        assert_eq!(
+            DestatisValue::<f64>::parse("1234,6"),
+            Ok(DestatisValue::new(1234.6))
+        );
+    }
+
+    #[test]
+    fn rejects_counts_out_of_range() {
+        assert!(DestatisValue::<i64>::from_cell(&Data::Float(f64::NAN)).is_err());
+        assert!(DestatisValue::<i64>::from_cell(&Data::Float(f64::INFINITY)).is_err());
+        assert!(DestatisValue::<i64>::from_cell(&Data::Float(1e30)).is_err());
+        assert!(DestatisValue::<i64>::from_cell(&Data::Float(-1e30)).is_err());
+    }
+}
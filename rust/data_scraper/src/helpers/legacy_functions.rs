@@ -0,0 +1,531 @@
+//! Extractors for the Monatsbericht Tourismus editions from 2002 to 2022. Those only
+//! contain formatted tables at fixed positions instead of the `csv-*` sheets.
+//!
+//! The sheets and rows are the ones used by the scripts in `historical_data_preprocessing`.
+
+use std::fmt::{Display, Formatter};
+
+use calamine::{Data, Range};
+use serde::{Deserialize, Serialize};
+
+use crate::events::YearMonth;
+use crate::helpers::{
+    DestatisNumber, DestatisValue, UebernachtungenNachHerkunftslandStruct,
+    UebernachtungenProLandStruct,
+};
+use crate::translate_to_month;
+
+pub const LAENDER: [&str; 16] = [
+    "Baden-Württemberg",
+    "Bayern",
+    "Berlin",
+    "Brandenburg",
+    "Bremen",
+    "Hamburg",
+    "Hessen",
+    "Mecklenburg-Vorpommern",
+    "Niedersachsen",
+    "Nordrhein-Westfalen",
+    "Rheinland-Pfalz",
+    "Saarland",
+    "Sachsen",
+    "Sachsen-Anhalt",
+    "Schleswig-Holstein",
+    "Thüringen",
+];
+
+/// Column B to F: arrivals, their change, overnight stays, their change and the
+/// average length of stay.
+const VALUE_COLUMNS: std::ops::RangeInclusive<u32> = 1..=5;
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LegacyLayout {
+    /// Editions 2002-01 to 2005-12.
+    Monatsbericht2002,
+    /// Editions 2006-01 to 2008-12.
+    Monatsbericht2006,
+    /// Editions 2009-01 to 2022-12.
+    Monatsbericht2009,
+}
+
+/// A table at a fixed position, rows are 1-based like in Excel.
+#[derive(Debug, Clone, Copy)]
+pub struct LegacyTable {
+    pub sheet: &'static str,
+    pub first_row: u32,
+    pub last_row: u32,
+}
+
+impl LegacyLayout {
+    /// Arrivals and overnight stays by country of origin.
+    pub fn herkunftsland(&self) -> LegacyTable {
+        match self {
+            LegacyLayout::Monatsbericht2002 => LegacyTable {
+                sheet: "3.2",
+                first_row: 13,
+                last_row: 86,
+            },
+            LegacyLayout::Monatsbericht2006 => LegacyTable {
+                sheet: "4.2",
+                first_row: 13,
+                last_row: 86,
+            },
+            LegacyLayout::Monatsbericht2009 => LegacyTable {
+                sheet: "1.8",
+                first_row: 15,
+                last_row: 67,
+            },
+        }
+    }
+
+    /// Arrivals and overnight stays by Land, each Land followed by its rows by residence.
+    pub fn pro_land(&self) -> LegacyTable {
+        match self {
+            LegacyLayout::Monatsbericht2002 => LegacyTable {
+                sheet: "3.1",
+                first_row: 13,
+                last_row: 81,
+            },
+            LegacyLayout::Monatsbericht2006 => LegacyTable {
+                sheet: "4.1",
+                first_row: 13,
+                last_row: 81,
+            },
+            LegacyLayout::Monatsbericht2009 => LegacyTable {
+                sheet: "1.9",
+                first_row: 14,
+                last_row: 79,
+            },
+        }
+    }
+
+    pub fn sheet_names(&self) -> [&'static str; 2] {
+        [self.herkunftsland().sheet, self.pro_land().sheet]
+    }
+
+    /// First and last edition of the layout.
+    fn editions(&self) -> (YearMonth, YearMonth) {
+        let (first, last) = match self {
+            LegacyLayout::Monatsbericht2002 => (2002, 2005),
+            LegacyLayout::Monatsbericht2006 => (2006, 2008),
+            LegacyLayout::Monatsbericht2009 => (2009, 2022),
+        };
+        (
+            YearMonth {
+                year: first,
+                month: 1,
+            },
+            YearMonth {
+                year: last,
+                month: 12,
+            },
+        )
+    }
+
+    /// Whether the edition of the period was published in this layout.
+    pub fn covers(&self, period: YearMonth) -> bool {
+        let (first, last) = self.editions();
+        first <= period && period <= last
+    }
+}
+
+/// A legacy table doesn't hold what its layout says, e.g. the edition was formatted
+/// differently.
+#[derive(Debug, Serialize)]
+pub struct LegacyTableError {
+    pub sheet: String,
+    pub error: String,
+}
+
+impl Display for LegacyTableError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Sheet {} {}", self.sheet, self.error)
+    }
+}
+
+impl std::error::Error for LegacyTableError {}
+
+struct LegacyRow {
+    label: String,
+    ankuenfte: DestatisValue<i64>,
+    ankuenfte_veraenderung: DestatisValue<f64>,
+    uebernachtungen: DestatisValue<i64>,
+    uebernachtungen_veraenderung: DestatisValue<f64>,
+    aufenthaltsdauer: DestatisValue<f64>,
+}
+
+impl LegacyRow {
+    fn is_empty(&self) -> bool {
+        self.ankuenfte == DestatisValue::default()
+            && self.ankuenfte_veraenderung == DestatisValue::default()
+            && self.uebernachtungen == DestatisValue::default()
+            && self.uebernachtungen_veraenderung == DestatisValue::default()
+            && self.aufenthaltsdauer == DestatisValue::default()
+    }
+}
+
+pub fn extract_nach_herkunftsland(
+    range: &Range<Data>,
+    table: LegacyTable,
+    period: YearMonth,
+) -> Result<Vec<UebernachtungenNachHerkunftslandStruct>, LegacyTableError> {
+    Ok(legacy_rows(range, table)?
+        .into_iter()
+        .map(|x| UebernachtungenNachHerkunftslandStruct {
+            herkunftsregion: x.label,
+            jahr: period.year as i64,
+            monat: translate_to_month(period.month as i32),
+            ankuenfte_anzahl: x.ankuenfte,
+            ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent: x.ankuenfte_veraenderung,
+            uebernachtungen_anzahl: x.uebernachtungen,
+            uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent: x
+                .uebernachtungen_veraenderung,
+            durchsch_aufenthaltsdauer_tage: x.aufenthaltsdauer,
+        })
+        .collect())
+}
+
+/// Each Land starts with a row of its own, which holds its total unless it is followed
+/// by a "Zusammen" row. Germany as a whole is named "Gesamt", as in the old imports.
+pub fn extract_pro_land(
+    range: &Range<Data>,
+    table: LegacyTable,
+    period: YearMonth,
+) -> Result<Vec<UebernachtungenProLandStruct>, LegacyTableError> {
+    let mut land = None;
+    let mut rows = Vec::new();
+    for x in legacy_rows(range, table)? {
+        let wohnsitz = if let Some(name) = land_name(&x.label) {
+            land = Some(name);
+            "Gesamt"
+        } else if let Some(wohnsitz) = wohnsitz(&x.label) {
+            wohnsitz
+        } else {
+            continue;
+        };
+        let Some(land) = &land else {
+            continue;
+        };
+        if x.label == "Zusammen" {
+            rows.retain(|row: &UebernachtungenProLandStruct| {
+                !(row.land == *land && row.wohnsitz == "Gesamt")
+            });
+        }
+
+        rows.push(UebernachtungenProLandStruct {
+            land: land.clone(),
+            wohnsitz: wohnsitz.to_string(),
+            jahr: period.year as i64,
+            monat: translate_to_month(period.month as i32),
+            ankuenfte_anzahl: x.ankuenfte,
+            ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent: x.ankuenfte_veraenderung,
+            uebernachtungen_anzahl: x.uebernachtungen,
+            uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent: x
+                .uebernachtungen_veraenderung,
+            durchsch_aufenthaltsdauer_tage: x.aufenthaltsdauer,
+        });
+    }
+    Ok(rows)
+}
+
+/// The labelled rows of the table that hold at least one value. Headings and
+/// footnotes are skipped.
+fn legacy_rows(
+    range: &Range<Data>,
+    table: LegacyTable,
+) -> Result<Vec<LegacyRow>, LegacyTableError> {
+    let mut rows = Vec::new();
+    for row in table.first_row - 1..table.last_row {
+        let Some(label) = range.get_value((row, 0)).and_then(clean_label) else {
+            continue;
+        };
+
+        let column = |i| VALUE_COLUMNS.start() + i;
+        let legacy_row = LegacyRow {
+            label,
+            ankuenfte: cell_value(range, table, row, column(0))?,
+            ankuenfte_veraenderung: cell_value(range, table, row, column(1))?,
+            uebernachtungen: cell_value(range, table, row, column(2))?,
+            uebernachtungen_veraenderung: cell_value(range, table, row, column(3))?,
+            aufenthaltsdauer: cell_value(range, table, row, column(4))?,
+        };
+        if legacy_row.is_empty() {
+            continue;
+        }
+        rows.push(legacy_row);
+    }
+
+    if rows.is_empty() {
+        return Err(LegacyTableError {
+            sheet: table.sheet.to_string(),
+            error: format!(
+                "has no values in rows {} to {}",
+                table.first_row, table.last_row
+            ),
+        });
+    }
+    Ok(rows)
+}
+
+/// Labels are padded with dots or ellipses up to the first value column.
+fn clean_label(cell: &Data) -> Option<String> {
+    let Data::String(label) = cell else {
+        return None;
+    };
+    let label = label
+        .trim()
+        .trim_end_matches(['.', '…', ' ', ':'])
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    match label.as_str() {
+        "" => None,
+        "Schleswig Holstein" => Some("Schleswig-Holstein".to_string()),
+        _ => Some(label),
+    }
+}
+
+fn cell_value<T: DestatisNumber>(
+    range: &Range<Data>,
+    table: LegacyTable,
+    row: u32,
+    column: u32,
+) -> Result<DestatisValue<T>, LegacyTableError> {
+    let cell = range.get_value((row, column)).unwrap_or(&Data::Empty);
+    DestatisValue::from_cell(cell).map_err(|e| LegacyTableError {
+        sheet: table.sheet.to_string(),
+        error: format!("row {} column {}: {}", row + 1, column + 1, e),
+    })
+}
+
+fn land_name(label: &str) -> Option<String> {
+    match label {
+        "Bundesgebiet insgesamt" | "Bundesgebiet" | "Insgesamt" => Some("Gesamt".to_string()),
+        x if LAENDER.contains(&x) => Some(x.to_string()),
+        _ => None,
+    }
+}
+
+fn wohnsitz(label: &str) -> Option<&'static str> {
+    match label {
+        "Zusammen" => Some("Gesamt"),
+        "Deutschland" | "Bundesrep. Deutschland" | "Inland" => Some("Inland"),
+        "Ausland" | "Anderer Wohnsitz" => Some("Ausland"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::QualityFlag;
+
+    const LAYOUTS: [LegacyLayout; 3] = [
+        LegacyLayout::Monatsbericht2002,
+        LegacyLayout::Monatsbericht2006,
+        LegacyLayout::Monatsbericht2009,
+    ];
+    const PERIOD: YearMonth = YearMonth {
+        year: 2007,
+        month: 3,
+    };
+
+    fn text(x: &str) -> Data {
+        Data::String(x.to_string())
+    }
+
+    fn values(ankuenfte: f64, uebernachtungen: f64) -> [Data; 5] {
+        [
+            Data::Float(ankuenfte),
+            Data::Float(1.5),
+            Data::Float(uebernachtungen),
+            text("-2,5"),
+            Data::Float(2.4),
+        ]
+    }
+
+    /// A sheet with the rows placed at the first row of the table, and a footnote
+    /// right below the table that must not be read.
+    fn sheet(table: LegacyTable, rows: &[(&str, Option<[Data; 5]>)]) -> Range<Data> {
+        let last_row = table.last_row + 1;
+        let mut range = Range::new((0, 0), (last_row - 1, 5));
+        range.set_value((0, 0), text("Tabelle"));
+        for (i, (label, values)) in rows.iter().enumerate() {
+            let row = table.first_row - 1 + i as u32;
+            range.set_value((row, 0), text(label));
+            for (value, column) in values.iter().flatten().zip(VALUE_COLUMNS) {
+                range.set_value((row, column), value.clone());
+            }
+        }
+        range.set_value((last_row - 1, 0), text("Bayern"));
+        range.set_value((last_row - 1, 1), Data::Float(1.0));
+        range
+    }
+
+    #[test]
+    fn cleans_labels() {
+        assert_eq!(
+            clean_label(&text("Bayern ..........")),
+            Some("Bayern".to_string())
+        );
+        assert_eq!(
+            clean_label(&text("  Inland …… :")),
+            Some("Inland".to_string())
+        );
+        assert_eq!(
+            clean_label(&text("Bundesrep.   Deutschland ...")),
+            Some("Bundesrep. Deutschland".to_string())
+        );
+        assert_eq!(
+            clean_label(&text("Schleswig Holstein .....")),
+            Some("Schleswig-Holstein".to_string())
+        );
+        assert_eq!(clean_label(&text(" ..... ")), None);
+        assert_eq!(clean_label(&Data::Float(1.0)), None);
+        assert_eq!(clean_label(&Data::Empty), None);
+    }
+
+    #[test]
+    fn extracts_pro_land() {
+        for layout in LAYOUTS {
+            let table = layout.pro_land();
+            let range = sheet(
+                table,
+                &[
+                    ("Ankünfte und Übernachtungen", None),
+                    ("Deutschland", Some(values(5.0, 6.0))),
+                    ("Bundesgebiet insgesamt ....", Some(values(100.0, 300.0))),
+                    ("Deutschland", Some(values(80.0, 200.0))),
+                    ("Ausland", Some(values(20.0, 100.0))),
+                    ("Bayern ....", Some(values(1.0, 2.0))),
+                    ("Zusammen", Some(values(30.0, 90.0))),
+                    ("Bundesrep. Deutschland", Some(values(25.0, 70.0))),
+                    ("Anderer Wohnsitz", Some(values(5.0, 20.0))),
+                    ("Schleswig Holstein", Some(values(10.0, 40.0))),
+                    ("Inland", Some(values(9.0, 30.0))),
+                    ("Sonstige", Some(values(1.0, 1.0))),
+                ],
+            );
+
+            let rows = extract_pro_land(&range, table, PERIOD).unwrap();
+            let found = rows
+                .iter()
+                .map(|x| {
+                    (
+                        x.land.as_str(),
+                        x.wohnsitz.as_str(),
+                        x.ankuenfte_anzahl.value,
+                    )
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                found,
+                [
+                    ("Gesamt", "Gesamt", Some(100)),
+                    ("Gesamt", "Inland", Some(80)),
+                    ("Gesamt", "Ausland", Some(20)),
+                    ("Bayern", "Gesamt", Some(30)),
+                    ("Bayern", "Inland", Some(25)),
+                    ("Bayern", "Ausland", Some(5)),
+                    ("Schleswig-Holstein", "Gesamt", Some(10)),
+                    ("Schleswig-Holstein", "Inland", Some(9)),
+                ],
+                "{:?}",
+                layout
+            );
+            assert_eq!(rows[0].jahr, 2007);
+            assert_eq!(rows[0].monat, translate_to_month(3));
+            assert_eq!(rows[0].uebernachtungen_anzahl, DestatisValue::new(300));
+            assert_eq!(
+                rows[0].uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent,
+                DestatisValue::new(-2.5)
+            );
+        }
+    }
+
+    #[test]
+    fn extracts_nach_herkunftsland() {
+        for layout in LAYOUTS {
+            let table = layout.herkunftsland();
+            let mut placeholder = values(3.0, 4.0);
+            placeholder[2] = text(".");
+            let range = sheet(
+                table,
+                &[
+                    ("Herkunftsland", None),
+                    ("Insgesamt .........", Some(values(100.0, 300.0))),
+                    ("Niederlande ....", Some(values(10.0, 50.0))),
+                    ("Island", Some(placeholder)),
+                ],
+            );
+
+            let rows = extract_nach_herkunftsland(&range, table, PERIOD).unwrap();
+            assert_eq!(
+                rows.iter()
+                    .map(|x| x.herkunftsregion.as_str())
+                    .collect::<Vec<_>>(),
+                ["Insgesamt", "Niederlande", "Island"],
+                "{:?}",
+                layout
+            );
+            assert_eq!(rows[1].ankuenfte_anzahl, DestatisValue::new(10));
+            assert_eq!(rows[2].uebernachtungen_anzahl.value, None);
+            assert_eq!(
+                rows[2].uebernachtungen_anzahl.flag,
+                Some(QualityFlag::NotAvailable)
+            );
+        }
+    }
+
+    #[test]
+    fn a_table_without_values_fails() {
+        let table = LegacyLayout::Monatsbericht2009.herkunftsland();
+        let range = sheet(table, &[("Herkunftsland", None)]);
+        let error = extract_nach_herkunftsland(&range, table, PERIOD).unwrap_err();
+        assert_eq!(error.sheet, "1.8");
+        assert!(error.error.starts_with("has no values"), "{}", error);
+    }
+
+    #[test]
+    fn a_bad_cell_fails_with_its_position() {
+        let table = LegacyLayout::Monatsbericht2002.pro_land();
+        let mut bad = values(1.0, 2.0);
+        bad[0] = text("viele");
+        let range = sheet(table, &[("Bayern", Some(bad))]);
+        let error = extract_pro_land(&range, table, PERIOD).unwrap_err();
+        assert_eq!(error.sheet, "3.1");
+        assert!(
+            error
+                .error
+                .starts_with(&format!("row {} column 2", table.first_row)),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn a_fractional_count_fails_with_its_position() {
+        let table = LegacyLayout::Monatsbericht2009.herkunftsland();
+        let range = sheet(table, &[("Niederlande", Some(values(1.0, 2.6)))]);
+        let error = extract_nach_herkunftsland(&range, table, PERIOD).unwrap_err();
+        assert!(
+            error
+                .error
+                .starts_with(&format!("row {} column 4", table.first_row)),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn layouts_cover_their_editions() {
+        let month = |year, month| YearMonth { year, month };
+        assert!(LegacyLayout::Monatsbericht2002.covers(month(2002, 1)));
+        assert!(LegacyLayout::Monatsbericht2002.covers(month(2005, 12)));
+        assert!(!LegacyLayout::Monatsbericht2002.covers(month(2006, 1)));
+        assert!(LegacyLayout::Monatsbericht2006.covers(month(2006, 1)));
+        assert!(LegacyLayout::Monatsbericht2009.covers(month(2022, 12)));
+        assert!(!LegacyLayout::Monatsbericht2009.covers(month(2023, 1)));
+    }
+}
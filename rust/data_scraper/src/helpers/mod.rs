@@ -1,7 +1,9 @@
-pub use api_functions::*;
+pub use destatis_value::*;
+pub use legacy_functions::*;
 pub use scrape_functions::*;
 pub use xlsx_functions::*;
 
-mod api_functions;
+mod destatis_value;
+mod legacy_functions;
 mod scrape_functions;
 mod xlsx_functions;
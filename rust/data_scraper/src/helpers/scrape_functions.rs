@@ -1,6 +1,13 @@
-use log::trace;
-use reqwest::Client;
-use scraper::Selector;
+use std::collections::BTreeMap;
+
+use log::{trace, warn};
+use scraper::{ElementRef, Selector};
+
+use crate::mycore::{MyCoReClient, SeriesEdition};
+
+const EDITION_ID_PREFIX: &str = "DEHeft_mods_";
+/// Guards against a "next" link that leads in a circle.
+const MAX_HTML_PAGES: usize = 50;
 
 #[derive(Debug)]
 #[allow(dead_code)]
@@ -11,64 +18,264 @@ pub struct TableOfContent {
     // Apparently, there is an ID that we can use to download the file.
     pub mods_id: String,
 }
-pub async fn get_table_of_contents(client: &Client) -> Result<Vec<TableOfContent>, anyhow::Error> {
-    let initial_page_content = client
-        .get("https://www.statistischebibliothek.de/mir/receive/DESerie_mods_00007671")
-        .send()
-        .await
-        .map_err(|e| anyhow::anyhow!("Failed to fetch initial page: {}", e))?;
-
-    let initial_page_text = initial_page_content.text().await?;
-    let document = scraper::Html::parse_document(&initial_page_text);
-    let main_body = match document
-        /* We can just unwrap this because we know the selector is correct. */
-        .select(&Selector::parse("#main_col > div:nth-child(2) > ul").unwrap())
-        .next()
-    {
-        None => {
-            return Err(anyhow::anyhow!("Failed to find main body"));
+
+/// Lists every edition of a series, e.g. `DESerie_mods_00007671`, oldest first.
+///
+/// The search API is asked first. If it fails, the series page is scraped instead,
+/// which breaks more easily when the layout changes.
+pub async fn get_table_of_contents(
+    mycore: &MyCoReClient<'_>,
+    series_id: &str,
+) -> Result<Vec<TableOfContent>, anyhow::Error> {
+    let editions = match mycore.series_editions(series_id).await {
+        Ok(editions) if !editions.is_empty() => editions,
+        Ok(_) => {
+            warn!(
+                "Search API returned no editions for {}, scraping the series page",
+                series_id
+            );
+            scrape_series_editions(mycore, series_id).await?
+        }
+        Err(e) => {
+            warn!("Search API failed, scraping the series page: {}", e);
+            scrape_series_editions(mycore, series_id).await?
         }
-        Some(x) => x,
     };
 
-    let mut table_of_contents = Vec::new();
-    for x in main_body.select(&Selector::parse("li").unwrap()) {
-        let inner_text = x.text().collect::<String>().trim().to_string();
-        trace!("Inner text: {}", inner_text);
-        // 2024,02
-        let year = inner_text[0..4]
-            .parse::<i32>()
-            .map_err(|e| anyhow::anyhow!("Failed to parse year: {}", e))?;
-        let month = inner_text[5..7]
-            .parse::<i32>()
-            .map_err(|e| anyhow::anyhow!("Failed to parse month: {}", e))?;
-
-        let url = x
-            .select(&Selector::parse("a").unwrap())
-            .next()
-            .ok_or(anyhow::anyhow!("Failed to find A Tag"))?
-            .attr("href")
-            .ok_or(anyhow::anyhow!("Failed to find href url"))?
-            .to_string();
-
-        let mods_id = url
-            .split("/")
-            .last()
-            .ok_or(anyhow::anyhow!("Failed to find mod id"))?
-            .to_string();
-        if !mods_id.starts_with("DEHeft_mods_") {
-            return Err(anyhow::anyhow!("Invalid mod id for url: {}", url));
+    // Keyed by id, the HTML pages may list an edition more than once.
+    let mut table_of_contents = BTreeMap::new();
+    for edition in editions {
+        if !edition.mods_id.starts_with(EDITION_ID_PREFIX) {
+            warn!("Skipping {}, it is not an edition", edition.mods_id);
+            continue;
         }
+        let Some((year, month)) = edition.label.as_deref().and_then(parse_edition_label) else {
+            warn!(
+                "Skipping {}, can't read year and month from {:?}",
+                edition.mods_id, edition.label
+            );
+            continue;
+        };
 
-        trace!("Year: {}, Month: {}, URL: {}", year, month, url);
+        trace!("Year: {}, Month: {}, Id: {}", year, month, edition.mods_id);
+        table_of_contents.insert(
+            edition.mods_id.clone(),
+            TableOfContent {
+                year,
+                month,
+                url: mycore.receive_url(&edition.mods_id),
+                mods_id: edition.mods_id,
+            },
+        );
+    }
 
-        table_of_contents.push(TableOfContent {
-            year,
-            month,
-            url,
-            mods_id,
-        });
+    if table_of_contents.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Found no editions with a readable date for series {}",
+            series_id
+        ));
     }
 
+    let mut table_of_contents = table_of_contents.into_values().collect::<Vec<_>>();
+    table_of_contents.sort_by_key(|x| (x.year, x.month));
     Ok(table_of_contents)
 }
+
+/// Collects the links to editions from the series page and the pages its "next"
+/// links lead to.
+async fn scrape_series_editions(
+    mycore: &MyCoReClient<'_>,
+    series_id: &str,
+) -> Result<Vec<SeriesEdition>, anyhow::Error> {
+    /* We can just unwrap these because we know the selectors are correct. */
+    let edition_links = Selector::parse(&format!("a[href*=\"{}\"]", EDITION_ID_PREFIX)).unwrap();
+    let next_link = Selector::parse("a[rel=\"next\"]").unwrap();
+
+    let mut editions = Vec::new();
+    let mut url = mycore.receive_url(series_id);
+    for _ in 0..MAX_HTML_PAGES {
+        let page_text = mycore
+            .page(&url)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to fetch series page {}: {}", url, e))?;
+        let document = scraper::Html::parse_document(&page_text);
+
+        let before = editions.len();
+        for link in document.select(&edition_links) {
+            let Some(href) = link.attr("href") else {
+                continue;
+            };
+            let Some(mods_id) = href
+                .split(['/', '?', '#'])
+                .find(|x| x.starts_with(EDITION_ID_PREFIX))
+            else {
+                continue;
+            };
+            editions.push(SeriesEdition {
+                mods_id: mods_id.to_string(),
+                label: Some(edition_label(link)),
+            });
+        }
+        if editions.len() == before {
+            return Err(anyhow::anyhow!(
+                "Series page {} contains no links to editions, the layout probably changed",
+                url
+            ));
+        }
+
+        let Some(next) = document
+            .select(&next_link)
+            .next()
+            .and_then(|x| x.attr("href"))
+        else {
+            return Ok(editions);
+        };
+        url = match reqwest::Url::parse(&url).and_then(|x| x.join(next)) {
+            Ok(next) => next.to_string(),
+            Err(e) => {
+                return Err(anyhow::anyhow!(
+                    "Invalid next page link {} on {}: {}",
+                    next,
+                    url,
+                    e
+                ))
+            }
+        };
+    }
+
+    Err(anyhow::anyhow!(
+        "Series page {} has more than {} pages",
+        series_id,
+        MAX_HTML_PAGES
+    ))
+}
+
+/// The link text if it holds the label, otherwise the text of the list entry the link is
+/// in. The entry may hold more than the label, like the date of the upload.
+fn edition_label(link: ElementRef) -> String {
+    let text = |x: ElementRef| x.text().collect::<String>().trim().to_string();
+    let link_text = text(link);
+    if parse_edition_label(&link_text).is_some() {
+        return link_text;
+    }
+    link.ancestors()
+        .filter_map(ElementRef::wrap)
+        .find(|x| x.value().name() == "li")
+        .map(text)
+        .unwrap_or(link_text)
+}
+
+/// Reads year and month from labels like "2024,02", "2024, 2" or "02/2024", leaving
+/// out remarks in parentheses like "(Stand 15.04.2024)".
+/// Returns `None` unless there is exactly one four digit year and one valid month.
+fn parse_edition_label(label: &str) -> Option<(i32, i32)> {
+    let mut depth = 0usize;
+    let mut outside = String::with_capacity(label.len());
+    for x in label.chars() {
+        match x {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            _ if depth == 0 => {
+                outside.push(x);
+                continue;
+            }
+            _ => continue,
+        }
+        // Keeps the numbers on both sides of a remark apart.
+        outside.push(' ');
+    }
+
+    let numbers = outside
+        .split(|x: char| !x.is_ascii_digit())
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>();
+
+    let mut years = numbers.iter().filter(|x| x.len() == 4);
+    let year = years.next()?.parse::<i32>().ok()?;
+    if years.next().is_some() {
+        return None;
+    }
+
+    let mut months = numbers.iter().filter(|x| x.len() <= 2);
+    let month = months.next()?.parse::<i32>().ok()?;
+    if months.next().is_some() || !(1..=12).contains(&month) {
+        return None;
+    }
+    Some((year, month))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn label_of_link(html: &str) -> String {
+        let document = scraper::Html::parse_fragment(html);
+        let link = Selector::parse("a").unwrap();
+        edition_label(document.select(&link).next().unwrap())
+    }
+
+    #[test]
+    fn parses_the_label_formats() {
+        assert_eq!(parse_edition_label("2024,02"), Some((2024, 2)));
+        assert_eq!(parse_edition_label("2024, 2"), Some((2024, 2)));
+        assert_eq!(parse_edition_label("02/2024"), Some((2024, 2)));
+        assert_eq!(parse_edition_label(" Ausgabe 2024,12 "), Some((2024, 12)));
+    }
+
+    #[test]
+    fn short_and_empty_labels_have_no_date() {
+        assert_eq!(parse_edition_label(""), None);
+        assert_eq!(parse_edition_label(" "), None);
+        assert_eq!(parse_edition_label("2"), None);
+        assert_eq!(parse_edition_label("24,2"), None);
+        assert_eq!(parse_edition_label("2024"), None);
+        assert_eq!(parse_edition_label("2024,"), None);
+    }
+
+    #[test]
+    fn parses_labels_with_non_ascii_text() {
+        assert_eq!(parse_edition_label("März 2024, 3"), Some((2024, 3)));
+        assert_eq!(
+            parse_edition_label("2024,02 – Übernachtungen"),
+            Some((2024, 2))
+        );
+        assert_eq!(parse_edition_label("２０２４,０２"), None);
+        assert_eq!(parse_edition_label("Ausgabe für Februar"), None);
+    }
+
+    #[test]
+    fn remarks_in_parentheses_are_left_out() {
+        assert_eq!(
+            parse_edition_label("2024,02 (Stand 15.04.2024)"),
+            Some((2024, 2))
+        );
+        assert_eq!(
+            parse_edition_label("(Stand: 3. Mai 2024) 2024, 3"),
+            Some((2024, 3))
+        );
+    }
+
+    #[test]
+    fn labels_with_other_numbers_have_no_date() {
+        assert_eq!(parse_edition_label("2024,02 Stand 15.04.2024"), None);
+        assert_eq!(parse_edition_label("2023,12 2024,01"), None);
+        assert_eq!(parse_edition_label("2024,02 Teil 3"), None);
+        assert_eq!(parse_edition_label("2024,13"), None);
+        assert_eq!(parse_edition_label("2024,00"), None);
+    }
+
+    #[test]
+    fn prefers_the_link_text() {
+        assert_eq!(
+            label_of_link(
+                "<ul><li><a href=\"/DEHeft_mods_1\">2024,02</a> hochgeladen 15.04.2024</li></ul>"
+            ),
+            "2024,02"
+        );
+        assert_eq!(
+            label_of_link("<ul><li>2024,02 <a href=\"/DEHeft_mods_1\">Download</a></li></ul>"),
+            "2024,02 Download"
+        );
+        assert_eq!(label_of_link("<a href=\"/DEHeft_mods_1\">PDF</a>"), "PDF");
+    }
+}
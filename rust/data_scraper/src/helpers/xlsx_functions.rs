@@ -1,43 +1,231 @@
 use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
 use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
 
-use calamine::{open_workbook_auto_from_rs, Data, Range, Reader};
-use log::{info, trace};
+use calamine::{open_workbook_from_rs, Data, Range, Reader, Sheets};
+use log::{trace, warn};
 use serde::de::DeserializeOwned;
 use serde::{de, Deserialize, Deserializer, Serialize};
 use serde_json::Value;
+use strsim::normalized_damerau_levenshtein;
+
+use crate::helpers::{DestatisNumber, DestatisValue};
+
+/// Compound file header of the binary Excel format used before 2007.
+const XLS_MAGIC: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+/// Local file header of a zip archive, which an xlsx file is.
+const XLSX_MAGIC: [u8; 4] = [0x50, 0x4B, 0x03, 0x04];
+/// How similar a missing and an unexpected header must be to be reported as renamed.
+const RENAME_SIMILARITY: f64 = 0.7;
+
+pub const NACH_HERKUNFTSLAND_HEADERS: [&str; 8] = [
+    "Herkunftsregion",
+    "Jahr",
+    "Monat",
+    "Ankuenfte_Anzahl",
+    "Ankuenfte_Veraenderung_zum_Vorjahreszeitraum_Prozent",
+    "Uebernachtungen_Anzahl",
+    "Uebernachtungen_Veraenderung_zum_Vorjahreszeitraum_Prozent",
+    "Durchsch_Aufenthaltsdauer_Tage",
+];
+
+pub const PRO_LAND_HEADERS: [&str; 9] = [
+    "Land",
+    "Wohnsitz",
+    "Jahr",
+    "Monat",
+    "Ankuenfte_Anzahl",
+    "Ankuenfte_Veraenderung_zum_Vorjahreszeitraum_Prozent",
+    "Uebernachtungen_Anzahl",
+    "Uebernachtungen_Veraenderung_zum_Vorjahreszeitraum_Prozent",
+    "Durchsch_Aufenthaltsdauer_Tage",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkbookFormat {
+    Xlsx,
+    Xls,
+}
 
-pub struct XlsxData {
-    pub uebernachtungen_nach_herkunftsland: Range<Data>,
-    pub uebernachtungen_pro_land: Range<Data>,
+impl Display for WorkbookFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorkbookFormat::Xlsx => write!(f, "xlsx"),
+            WorkbookFormat::Xls => write!(f, "xls"),
+        }
+    }
 }
 
-pub async fn load_xlsx_file(file: &File) -> Result<XlsxData, anyhow::Error> {
-    let mut workbook = open_workbook_auto_from_rs(file)
-        .map_err(|e| anyhow::anyhow!("Failed to open workbook: {}", e))?;
-    if workbook.sheet_names().is_empty() {
-        return Err(anyhow::anyhow!("No sheets found in workbook"));
+impl WorkbookFormat {
+    /// Detects the format from the first bytes, the file name can't be trusted.
+    pub fn detect(mut file: &File) -> Result<Self, anyhow::Error> {
+        let mut magic = [0; 8];
+        file.seek(SeekFrom::Start(0))?;
+        let read = file.read(&mut magic)?;
+        file.seek(SeekFrom::Start(0))?;
+
+        if magic[..read] == XLS_MAGIC {
+            Ok(WorkbookFormat::Xls)
+        } else if magic[..read].starts_with(&XLSX_MAGIC) {
+            Ok(WorkbookFormat::Xlsx)
+        } else {
+            Err(anyhow::anyhow!(
+                "Not a workbook, the file starts with {:?}",
+                String::from_utf8_lossy(&magic[..read])
+            ))
+        }
     }
+}
 
-    // We need sheet csv-45412-07 and csv-45412-08
-    if !workbook.sheet_names().contains(&"csv-45412-07".to_string()) {
-        return Err(anyhow::anyhow!("Sheet csv-45412-07 not found"));
+pub struct Workbook<'a> {
+    pub format: WorkbookFormat,
+    sheets: Sheets<&'a File>,
+}
+
+impl<'a> Workbook<'a> {
+    pub fn open(file: &'a File) -> Result<Self, anyhow::Error> {
+        let format = WorkbookFormat::detect(file)?;
+        let sheets = match format {
+            WorkbookFormat::Xlsx => open_workbook_from_rs(file)
+                .map(Sheets::Xlsx)
+                .map_err(|e| e.to_string()),
+            WorkbookFormat::Xls => open_workbook_from_rs(file)
+                .map(Sheets::Xls)
+                .map_err(|e| e.to_string()),
+        }
+        .map_err(|e| anyhow::anyhow!("Failed to open {} workbook: {}", format, e))?;
+        if sheets.sheet_names().is_empty() {
+            return Err(anyhow::anyhow!("No sheets found in workbook"));
+        }
+        Ok(Self { format, sheets })
     }
-    if !workbook.sheet_names().contains(&"csv-45412-08".to_string()) {
-        return Err(anyhow::anyhow!("Sheet csv-45412-08 not found"));
+
+    pub fn has_sheets(&self, sheet_names: &[&str]) -> bool {
+        let available = self.sheets.sheet_names();
+        sheet_names
+            .iter()
+            .all(|&x| available.iter().any(|y| y == x))
+    }
+
+    /// Reads the given sheets, keyed by sheet name.
+    pub fn load_sheets(
+        &mut self,
+        sheet_names: &[&str],
+    ) -> Result<HashMap<String, Range<Data>>, anyhow::Error> {
+        let mut sheets = HashMap::new();
+        for &sheet_name in sheet_names {
+            if !self.has_sheets(&[sheet_name]) {
+                return Err(anyhow::anyhow!("Sheet {} not found", sheet_name));
+            }
+            let range = self
+                .sheets
+                .worksheet_range(sheet_name)
+                .map_err(|e| anyhow::anyhow!("Failed to map range for {}: {}", sheet_name, e))?;
+            sheets.insert(sheet_name.to_string(), range);
+        }
+        Ok(sheets)
+    }
+}
+
+/// The headers of a sheet don't match the ones its parser expects.
+#[derive(Debug, Serialize)]
+pub struct LayoutDrift {
+    pub sheet: String,
+    pub missing: Vec<String>,
+    pub renamed: Vec<RenamedHeader>,
+    pub extra: Vec<String>,
+}
+
+/// A missing header with a similar unexpected one, most likely the same column.
+#[derive(Debug, Serialize)]
+pub struct RenamedHeader {
+    pub expected: String,
+    pub found: String,
+}
+
+impl Display for LayoutDrift {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Sheet {} doesn't have the expected headers", self.sheet)?;
+        if !self.missing.is_empty() {
+            write!(f, ", missing: {}", self.missing.join(", "))?;
+        }
+        for x in &self.renamed {
+            write!(f, ", {} was probably renamed to {}", x.expected, x.found)?;
+        }
+        if !self.extra.is_empty() {
+            write!(f, ", unexpected: {}", self.extra.join(", "))?;
+        }
+        Ok(())
     }
+}
 
-    let uebernachtungen_nach_herkunftsland = workbook
-        .worksheet_range("csv-45412-07")
-        .map_err(|e| anyhow::anyhow!("Failed to map range for csv-45412-07: {}", e))?;
+impl std::error::Error for LayoutDrift {}
 
-    let uebernachtungen_pro_land = workbook
-        .worksheet_range("csv-45412-08")
-        .map_err(|e| anyhow::anyhow!("Failed to map range for csv-45412-08: {}", e))?;
-    Ok(XlsxData {
-        uebernachtungen_nach_herkunftsland,
-        uebernachtungen_pro_land,
-    })
+/// The trimmed cells of the first row.
+pub fn headers(range: &Range<Data>) -> Vec<String> {
+    range
+        .rows()
+        .next()
+        .map(|x| x.iter().map(|x| x.to_string().trim().to_string()).collect())
+        .unwrap_or_default()
+}
+
+/// Checks that every expected header is present. Unexpected headers alone are only
+/// logged, the parsers ignore columns they don't know.
+pub fn validate_headers(
+    sheet: &str,
+    headers: &[String],
+    expected: &[&str],
+) -> Result<(), LayoutDrift> {
+    let missing = expected
+        .iter()
+        .filter(|x| !headers.iter().any(|y| y == *x))
+        .collect::<Vec<_>>();
+    let mut extra = headers
+        .iter()
+        .filter(|x| !x.is_empty() && !expected.contains(&x.as_str()))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if missing.is_empty() {
+        if !extra.is_empty() {
+            warn!(
+                "Sheet {} has unexpected headers: {}",
+                sheet,
+                extra.join(", ")
+            );
+        }
+        return Ok(());
+    }
+
+    let mut drift = LayoutDrift {
+        sheet: sheet.to_string(),
+        missing: Vec::new(),
+        renamed: Vec::new(),
+        extra: Vec::new(),
+    };
+    for expected in missing {
+        let closest = extra
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                let similarity =
+                    normalized_damerau_levenshtein(&expected.to_lowercase(), &x.to_lowercase());
+                (i, similarity)
+            })
+            .filter(|(_, similarity)| *similarity >= RENAME_SIMILARITY)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        match closest {
+            Some((i, _)) => drift.renamed.push(RenamedHeader {
+                expected: expected.to_string(),
+                found: extra.remove(i),
+            }),
+            None => drift.missing.push(expected.to_string()),
+        }
+    }
+    drift.extra = extra;
+    Err(drift)
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -51,32 +239,20 @@ pub struct UebernachtungenNachHerkunftslandStruct {
     #[serde(rename = "Monat")]
     pub monat: String,
 
-    #[serde(rename = "Ankuenfte_Anzahl", deserialize_with = "de_as_i64_option")]
-    pub ankuenfte_anzahl: Option<i64>,
+    #[serde(rename = "Ankuenfte_Anzahl")]
+    pub ankuenfte_anzahl: DestatisValue<i64>,
 
-    #[serde(
-        rename = "Ankuenfte_Veraenderung_zum_Vorjahreszeitraum_Prozent",
-        deserialize_with = "de_as_f64_option"
-    )]
-    pub ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent: Option<f64>,
+    #[serde(rename = "Ankuenfte_Veraenderung_zum_Vorjahreszeitraum_Prozent")]
+    pub ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent: DestatisValue<f64>,
 
-    #[serde(
-        rename = "Uebernachtungen_Anzahl",
-        deserialize_with = "de_as_i64_option"
-    )]
-    pub uebernachtungen_anzahl: Option<i64>,
+    #[serde(rename = "Uebernachtungen_Anzahl")]
+    pub uebernachtungen_anzahl: DestatisValue<i64>,
 
-    #[serde(
-        rename = "Uebernachtungen_Veraenderung_zum_Vorjahreszeitraum_Prozent",
-        deserialize_with = "de_as_f64_option"
-    )]
-    pub uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent: Option<f64>,
+    #[serde(rename = "Uebernachtungen_Veraenderung_zum_Vorjahreszeitraum_Prozent")]
+    pub uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent: DestatisValue<f64>,
 
-    #[serde(
-        rename = "Durchsch_Aufenthaltsdauer_Tage",
-        deserialize_with = "de_as_f64_option"
-    )]
-    pub durchsch_aufenthaltsdauer_tage: Option<f64>,
+    #[serde(rename = "Durchsch_Aufenthaltsdauer_Tage")]
+    pub durchsch_aufenthaltsdauer_tage: DestatisValue<f64>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -93,120 +269,327 @@ pub struct UebernachtungenProLandStruct {
     #[serde(rename = "Monat")]
     pub monat: String,
 
-    #[serde(rename = "Ankuenfte_Anzahl", deserialize_with = "de_as_i64_option")]
-    pub ankuenfte_anzahl: Option<i64>,
+    #[serde(rename = "Ankuenfte_Anzahl")]
+    pub ankuenfte_anzahl: DestatisValue<i64>,
 
-    #[serde(
-        rename = "Ankuenfte_Veraenderung_zum_Vorjahreszeitraum_Prozent",
-        deserialize_with = "de_as_f64_option"
-    )]
-    pub ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent: Option<f64>,
+    #[serde(rename = "Ankuenfte_Veraenderung_zum_Vorjahreszeitraum_Prozent")]
+    pub ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent: DestatisValue<f64>,
 
-    #[serde(
-        rename = "Uebernachtungen_Anzahl",
-        deserialize_with = "de_as_i64_option"
-    )]
-    pub uebernachtungen_anzahl: Option<i64>,
+    #[serde(rename = "Uebernachtungen_Anzahl")]
+    pub uebernachtungen_anzahl: DestatisValue<i64>,
 
-    #[serde(
-        rename = "Uebernachtungen_Veraenderung_zum_Vorjahreszeitraum_Prozent",
-        deserialize_with = "de_as_f64_option"
-    )]
-    pub uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent: Option<f64>,
+    #[serde(rename = "Uebernachtungen_Veraenderung_zum_Vorjahreszeitraum_Prozent")]
+    pub uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent: DestatisValue<f64>,
 
-    #[serde(
-        rename = "Durchsch_Aufenthaltsdauer_Tage",
-        deserialize_with = "de_as_f64_option"
-    )]
-    pub durchsch_aufenthaltsdauer_tage: Option<f64>,
+    #[serde(rename = "Durchsch_Aufenthaltsdauer_Tage")]
+    pub durchsch_aufenthaltsdauer_tage: DestatisValue<f64>,
 }
 
-pub async fn parse_range<T: DeserializeOwned + Serialize>(
-    data: Range<Data>,
-) -> Result<Vec<T>, anyhow::Error> {
-    let mut rows = data.rows();
+/// What happens to a sheet when one of its rows can't be parsed.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RowErrorPolicy {
+    /// The first bad row fails the sheet.
+    #[default]
+    Fail,
+    /// Bad rows are left out of the sheet and quarantined.
+    Skip,
+    /// Every row is parsed and all bad rows are reported together, then the sheet fails.
+    Collect,
+}
 
-    let headers = rows
-        .next()
-        .ok_or(anyhow::anyhow!("No headers found"))?
-        .iter()
-        .map(|x| x.to_string().trim().to_string())
-        .collect::<Vec<String>>();
+/// A row that couldn't be parsed, the position is 1-based like in Excel.
+#[derive(Debug, Clone, Serialize)]
+pub struct RowError {
+    pub sheet: String,
+    pub row: u32,
+    /// Header of the cell that failed, `None` if the row as a whole didn't fit.
+    pub column: Option<String>,
+    pub value: Option<Value>,
+    pub message: String,
+    /// The whole row by header, so it can be inspected without the workbook.
+    pub raw_row: Value,
+}
+
+impl Display for RowError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Sheet {} row {}", self.sheet, self.row)?;
+        if let Some(column) = &self.column {
+            write!(f, " column {}", column)?;
+        }
+        if let Some(value) = &self.value {
+            write!(f, " value {}", value)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// The rows that failed a sheet, see `RowErrorPolicy`.
+#[derive(Debug, Serialize)]
+pub struct RowErrors {
+    pub sheet: String,
+    pub errors: Vec<RowError>,
+}
+
+impl Display for RowErrors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.errors.as_slice() {
+            [error] => write!(f, "{}", error),
+            [first, ..] => write!(
+                f,
+                "{} rows of sheet {} failed to parse, the first one: {}",
+                self.errors.len(),
+                self.sheet,
+                first
+            ),
+            [] => write!(f, "Sheet {} failed to parse", self.sheet),
+        }
+    }
+}
+
+impl std::error::Error for RowErrors {}
+
+/// A row that could be read, but doesn't belong in the sheet.
+#[derive(Debug)]
+pub struct RowMismatch {
+    /// Header of the offending cell.
+    pub column: &'static str,
+    pub message: String,
+}
+
+/// Deserializes every row below the header row into `T`, matching fields by header.
+/// Rows that `check` rejects are row errors like those that fail to deserialize.
+/// Returns the rows and, with `RowErrorPolicy::Skip`, the rows that were left out.
+pub fn parse_range<T: DeserializeOwned>(
+    sheet: &str,
+    data: &Range<Data>,
+    policy: RowErrorPolicy,
+    check: impl Fn(&T) -> Result<(), RowMismatch>,
+) -> Result<(Vec<T>, Vec<RowError>), anyhow::Error> {
+    let headers = headers(data);
+    if headers.is_empty() {
+        return Err(anyhow::anyhow!("No headers found"));
+    }
 
     trace!("{:?}", headers);
 
-    let mut data = Vec::new();
-    for row in rows {
-        let hashmap: HashMap<String, Value> = headers
-            .iter()
-            .cloned()
-            .zip(row.iter().map(|x1| into_value(x1.clone())))
-            .collect();
-        info!("{:?}", hashmap);
+    let first_row = data.start().map(|x| x.0).unwrap_or(0) + 1;
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    let mut skipped = Vec::new();
+    // The first row holds the headers.
+    for (cells, row) in data.rows().skip(1).zip(first_row + 1..) {
+        let deserializer = RowDeserializer {
+            headers: &headers,
+            cells,
+            position: 0,
+        };
+        let row_result = T::deserialize(deserializer).and_then(|x| match check(&x) {
+            Ok(()) => Ok(x),
+            Err(e) => Err(CellDeError {
+                value: headers
+                    .iter()
+                    .position(|x| x == e.column)
+                    .and_then(|i| cells.get(i))
+                    .map(|x| into_value(x.clone())),
+                column: Some(e.column.to_string()),
+                message: e.message,
+            }),
+        });
+        match row_result {
+            Ok(x) => rows.push(x),
+            Err(e) => {
+                let error = RowError {
+                    sheet: sheet.to_string(),
+                    row,
+                    column: e.column,
+                    value: e.value,
+                    message: e.message,
+                    raw_row: headers
+                        .iter()
+                        .zip(cells)
+                        .map(|(header, cell)| (header.clone(), into_value(cell.clone())))
+                        .collect::<serde_json::Map<_, _>>()
+                        .into(),
+                };
+                match policy {
+                    RowErrorPolicy::Fail => {
+                        return Err(RowErrors {
+                            sheet: sheet.to_string(),
+                            errors: vec![error],
+                        }
+                        .into())
+                    }
+                    RowErrorPolicy::Skip => {
+                        warn!("Skipping row: {}", error);
+                        skipped.push(error)
+                    }
+                    RowErrorPolicy::Collect => errors.push(error),
+                }
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(RowErrors {
+            sheet: sheet.to_string(),
+            errors,
+        }
+        .into());
+    }
+    Ok((rows, skipped))
+}
+
+#[derive(Debug)]
+struct CellDeError {
+    column: Option<String>,
+    value: Option<Value>,
+    message: String,
+}
 
-        let serialized = serde_json::to_string(&hashmap).unwrap();
-        let deserialized: T = serde_json::from_str(&serialized).unwrap();
+impl Display for CellDeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
 
-        data.push(deserialized);
+impl std::error::Error for CellDeError {}
+
+impl de::Error for CellDeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        CellDeError {
+            column: None,
+            value: None,
+            message: msg.to_string(),
+        }
     }
-    Ok(data)
 }
 
-fn de_as_i64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
-    match Value::deserialize(deserializer)? {
-        Value::Number(n) => {
-            if n.is_f64() {
-                Ok(n.as_f64().unwrap() as i64)
-            } else {
-                Ok(n.as_i64().unwrap())
+/// Hands a row to serde as a map from header to cell.
+struct RowDeserializer<'a> {
+    headers: &'a [String],
+    cells: &'a [Data],
+    position: usize,
+}
+
+impl<'de, 'a> de::Deserializer<'de> for RowDeserializer<'a> {
+    type Error = CellDeError;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de, 'a> de::MapAccess<'de> for RowDeserializer<'a> {
+    type Error = CellDeError;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        // Cells without a header can't belong to a field.
+        while self.position < self.headers.len().min(self.cells.len()) {
+            let header = self.headers[self.position].as_str();
+            if !header.is_empty() {
+                return seed
+                    .deserialize(de::value::StrDeserializer::<CellDeError>::new(header))
+                    .map(Some);
             }
+            self.position += 1;
         }
-        _ => Err(de::Error::custom("wrong type")),
+        Ok(None)
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let cell = &self.cells[self.position];
+        let header = &self.headers[self.position];
+        self.position += 1;
+        seed.deserialize(CellDeserializer(cell))
+            .map_err(|e| CellDeError {
+                column: Some(header.clone()),
+                value: Some(into_value(cell.clone())),
+                message: e.message,
+            })
     }
 }
 
-fn de_as_i64_option<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i64>, D::Error> {
-    Ok(match Value::deserialize(deserializer)? {
-        Value::Number(num) => {
-            // Parse as Option<i64> removing any decimal parts.
-            let value = if num.is_f64() {
-                num.as_f64().unwrap() as i64
-            } else {
-                num.as_i64().unwrap()
-            };
-            Some(value)
+struct CellDeserializer<'a>(&'a Data);
+
+impl<'de, 'a> de::Deserializer<'de> for CellDeserializer<'a> {
+    type Error = CellDeError;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Data::Empty => visitor.visit_unit(),
+            Data::Error(e) => Err(de::Error::custom(format!("cell error {}", e))),
+            Data::Bool(x) => visitor.visit_bool(*x),
+            Data::Int(x) => visitor.visit_i64(*x),
+            Data::Float(x) => visitor.visit_f64(*x),
+            Data::String(x) => visitor.visit_str(x),
+            Data::DateTime(x) => visitor.visit_string(x.to_string()),
+            Data::DateTimeIso(x) | Data::DurationIso(x) => visitor.visit_str(x),
         }
-        Value::String(s) => {
-            if s.eq(".") || s.eq("-") || s.eq("X") {
-                None
-            } else {
-                Some(s.parse().map_err(de::Error::custom)?)
-            }
+    }
+
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Data::Empty => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// A whole number, also when the cell holds it as a float or as text.
+fn de_as_i64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    struct WholeNumber;
+
+    impl<'de> de::Visitor<'de> for WholeNumber {
+        type Value = i64;
+
+        fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+            write!(f, "a whole number")
         }
-        _ => return Err(de::Error::custom("wrong type")),
-    })
-}
-
-fn de_as_f64_option<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
-    Ok(match Value::deserialize(deserializer)? {
-        Value::Number(num) => {
-            // Parse as Option<f64> removing any decimal parts.
-            let value = if num.is_f64() {
-                num.as_f64().unwrap()
-            } else {
-                num.as_i64().unwrap() as f64
-            };
-            Some(value)
-        }
-        Value::String(s) => {
-            if s.eq(".") || s.eq("-") || s.eq("X") {
-                None
-            } else {
-                Some(s.parse().map_err(de::Error::custom)?)
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<i64, E> {
+            Ok(value)
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<i64, E> {
+            i64::try_from(value).map_err(|_| E::custom(format!("{} is out of range", value)))
+        }
+
+        fn visit_f64<E: de::Error>(self, value: f64) -> Result<i64, E> {
+            i64::from_f64(value).map_err(E::custom)
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<i64, E> {
+            let text = value.trim();
+            match text.parse::<i64>() {
+                Ok(x) => Ok(x),
+                Err(_) => text
+                    .parse::<f64>()
+                    .map_err(|_| E::custom(format!("{:?} is not a number", value)))
+                    .and_then(|x| self.visit_f64(x)),
             }
         }
-        _ => return Err(de::Error::custom("wrong type")),
-    })
+    }
+
+    deserializer.deserialize_i64(WholeNumber)
 }
 
 pub fn into_value(data: Data) -> Value {
@@ -223,3 +606,280 @@ pub fn into_value(data: Data) -> Value {
         Data::DurationIso(x) => Value::from(x.to_string()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::QualityFlag;
+
+    fn range(rows: &[Vec<Data>]) -> Range<Data> {
+        let width = rows.iter().map(|x| x.len()).max().unwrap_or(1);
+        let mut range = Range::new((0, 0), (rows.len() as u32 - 1, width as u32 - 1));
+        for (i, row) in rows.iter().enumerate() {
+            for (j, cell) in row.iter().enumerate() {
+                range.set_value((i as u32, j as u32), cell.clone());
+            }
+        }
+        range
+    }
+
+    fn text(x: &str) -> Data {
+        Data::String(x.to_string())
+    }
+
+    fn header_row() -> Vec<Data> {
+        NACH_HERKUNFTSLAND_HEADERS.iter().map(|x| text(x)).collect()
+    }
+
+    fn row(herkunft: &str, ankuenfte: Data) -> Vec<Data> {
+        vec![
+            text(herkunft),
+            Data::Float(2024.0),
+            text("Januar"),
+            ankuenfte,
+            Data::Float(3.5),
+            Data::Float(2500.0),
+            text("-1,2"),
+            Data::Float(2.5),
+        ]
+    }
+
+    fn parse(
+        rows: &[Vec<Data>],
+        policy: RowErrorPolicy,
+    ) -> Result<(Vec<UebernachtungenNachHerkunftslandStruct>, Vec<RowError>), anyhow::Error> {
+        parse_range("csv-45412-07", &range(rows), policy, |_| Ok(()))
+    }
+
+    /// Good, bad, good, bad.
+    fn mixed_rows() -> Vec<Vec<Data>> {
+        vec![
+            header_row(),
+            row("Inland", Data::Float(1000.0)),
+            row("Ausland", text("abc")),
+            row("Insgesamt", Data::Float(1500.0)),
+            row("Europa", text("1.2.3")),
+        ]
+    }
+
+    #[test]
+    fn parses_a_good_row() {
+        let (rows, skipped) = parse(
+            &[header_row(), row("Inland", text("1 000 p"))],
+            RowErrorPolicy::Fail,
+        )
+        .unwrap();
+        assert!(skipped.is_empty());
+        let [row] = rows.as_slice() else {
+            panic!("expected one row, got {:?}", rows);
+        };
+        assert_eq!(row.herkunftsregion, "Inland");
+        assert_eq!(row.jahr, 2024);
+        assert_eq!(row.monat, "Januar");
+        assert_eq!(row.ankuenfte_anzahl.value, Some(1000));
+        assert_eq!(row.ankuenfte_anzahl.flag, Some(QualityFlag::Preliminary));
+        assert_eq!(row.uebernachtungen_anzahl, DestatisValue::new(2500));
+        assert_eq!(
+            row.uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent,
+            DestatisValue::new(-1.2)
+        );
+    }
+
+    #[test]
+    fn reports_the_bad_cell() {
+        let error = parse(
+            &[
+                header_row(),
+                row("Inland", Data::Float(1000.0)),
+                row("Ausland", text("abc")),
+            ],
+            RowErrorPolicy::Fail,
+        )
+        .unwrap_err();
+        let errors = error.downcast_ref::<RowErrors>().unwrap();
+        let [error] = errors.errors.as_slice() else {
+            panic!("expected one error, got {:?}", errors.errors);
+        };
+        assert_eq!(error.sheet, "csv-45412-07");
+        assert_eq!(error.row, 3);
+        assert_eq!(error.column.as_deref(), Some("Ankuenfte_Anzahl"));
+        assert_eq!(error.value, Some(Value::from("abc")));
+        assert_eq!(error.raw_row["Herkunftsregion"], Value::from("Ausland"));
+    }
+
+    #[test]
+    fn empty_trailing_cells_are_empty_values() {
+        let mut short = row("Inland", Data::Float(1000.0));
+        short.truncate(4);
+        short.resize(NACH_HERKUNFTSLAND_HEADERS.len(), Data::Empty);
+        let (rows, _) = parse(&[header_row(), short], RowErrorPolicy::Fail).unwrap();
+        assert_eq!(rows[0].ankuenfte_anzahl, DestatisValue::new(1000));
+        assert_eq!(rows[0].uebernachtungen_anzahl, DestatisValue::default());
+        assert_eq!(
+            rows[0].durchsch_aufenthaltsdauer_tage,
+            DestatisValue::default()
+        );
+    }
+
+    #[test]
+    fn a_row_cut_off_before_a_required_cell_fails() {
+        let mut short = row("Inland", Data::Float(1000.0));
+        short.truncate(2);
+        short.resize(NACH_HERKUNFTSLAND_HEADERS.len(), Data::Empty);
+        let error = parse(&[header_row(), short], RowErrorPolicy::Fail).unwrap_err();
+        let error = &error.downcast_ref::<RowErrors>().unwrap().errors[0];
+        assert_eq!(error.row, 2);
+        assert_eq!(error.column.as_deref(), Some("Monat"));
+        assert_eq!(error.value, Some(Value::Null));
+    }
+
+    #[test]
+    fn reads_the_year_as_a_number_or_as_text() {
+        for jahr in [
+            Data::Float(2024.0),
+            Data::Int(2024),
+            text("2024"),
+            text(" 2024 "),
+        ] {
+            let mut cells = row("Inland", Data::Float(1000.0));
+            cells[1] = jahr.clone();
+            let (rows, _) = parse(&[header_row(), cells], RowErrorPolicy::Fail).unwrap();
+            assert_eq!(rows[0].jahr, 2024, "{:?}", jahr);
+        }
+    }
+
+    #[test]
+    fn a_year_that_is_not_a_whole_number_is_a_row_error() {
+        for jahr in [Data::Float(2024.5), text("2024,5"), text("letztes Jahr")] {
+            let mut cells = row("Inland", Data::Float(1000.0));
+            cells[1] = jahr.clone();
+            let error = parse(&[header_row(), cells], RowErrorPolicy::Fail).unwrap_err();
+            let error = &error.downcast_ref::<RowErrors>().unwrap().errors[0];
+            assert_eq!(error.column.as_deref(), Some("Jahr"));
+            assert_eq!(error.value, Some(into_value(jahr)));
+        }
+    }
+
+    #[test]
+    fn rows_rejected_by_the_check_are_row_errors() {
+        let mut february = row("Ausland", Data::Float(500.0));
+        february[2] = text("Februar");
+        let (rows, skipped) = parse_range(
+            "csv-45412-07",
+            &range(&[header_row(), row("Inland", Data::Float(1000.0)), february]),
+            RowErrorPolicy::Skip,
+            |x: &UebernachtungenNachHerkunftslandStruct| match x.monat.as_str() {
+                "Januar" => Ok(()),
+                _ => Err(RowMismatch {
+                    column: "Monat",
+                    message: "Wrong month".to_string(),
+                }),
+            },
+        )
+        .unwrap();
+        assert_eq!(rows.len(), 1);
+        let [error] = skipped.as_slice() else {
+            panic!("expected one skipped row, got {:?}", skipped);
+        };
+        assert_eq!(error.row, 3);
+        assert_eq!(error.column.as_deref(), Some("Monat"));
+        assert_eq!(error.value, Some(Value::from("Februar")));
+        assert_eq!(error.message, "Wrong month");
+    }
+
+    #[test]
+    fn fail_stops_at_the_first_bad_row() {
+        let error = parse(&mixed_rows(), RowErrorPolicy::Fail).unwrap_err();
+        let errors = error.downcast_ref::<RowErrors>().unwrap();
+        assert_eq!(errors.errors.iter().map(|x| x.row).collect::<Vec<_>>(), [3]);
+    }
+
+    #[test]
+    fn skip_leaves_out_the_bad_rows() {
+        let (rows, skipped) = parse(&mixed_rows(), RowErrorPolicy::Skip).unwrap();
+        assert_eq!(
+            rows.iter()
+                .map(|x| x.herkunftsregion.as_str())
+                .collect::<Vec<_>>(),
+            ["Inland", "Insgesamt"]
+        );
+        assert_eq!(skipped.iter().map(|x| x.row).collect::<Vec<_>>(), [3, 5]);
+    }
+
+    #[test]
+    fn collect_reports_every_bad_row() {
+        let error = parse(&mixed_rows(), RowErrorPolicy::Collect).unwrap_err();
+        let errors = error.downcast_ref::<RowErrors>().unwrap();
+        assert_eq!(
+            errors.errors.iter().map(|x| x.row).collect::<Vec<_>>(),
+            [3, 5]
+        );
+        assert!(errors
+            .to_string()
+            .starts_with("2 rows of sheet csv-45412-07"));
+    }
+
+    fn headers_of(names: &[&str]) -> Vec<String> {
+        names.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn extra_headers_alone_are_fine() {
+        let mut headers = headers_of(&NACH_HERKUNFTSLAND_HEADERS);
+        headers.push("Fussnote".to_string());
+        headers.push(String::new());
+        assert!(validate_headers("s", &headers, &NACH_HERKUNFTSLAND_HEADERS).is_ok());
+    }
+
+    #[test]
+    fn classifies_missing_renamed_and_extra_headers() {
+        let headers = headers_of(&["Land", "Wohnort", "Jahr", "Ankünfte_Anzahl", "Quelle"]);
+        let drift = validate_headers(
+            "s",
+            &headers,
+            &["Land", "Wohnsitz", "Jahr", "Monat", "Ankuenfte_Anzahl"],
+        )
+        .unwrap_err();
+        assert_eq!(drift.sheet, "s");
+        assert_eq!(drift.missing, ["Wohnsitz", "Monat"]);
+        assert_eq!(
+            drift
+                .renamed
+                .iter()
+                .map(|x| (x.expected.as_str(), x.found.as_str()))
+                .collect::<Vec<_>>(),
+            [("Ankuenfte_Anzahl", "Ankünfte_Anzahl")]
+        );
+        assert_eq!(drift.extra, ["Wohnort", "Quelle"]);
+    }
+
+    #[test]
+    fn renamed_needs_the_similarity_cut_off() {
+        // Three edits in ten characters, exactly at the cut-off.
+        let drift =
+            validate_headers("s", &headers_of(&["abcdefgxyz"]), &["abcdefghij"]).unwrap_err();
+        assert_eq!(drift.renamed.len(), 1);
+        assert!(drift.missing.is_empty());
+
+        // Four edits are too many.
+        let drift =
+            validate_headers("s", &headers_of(&["abcdefwxyz"]), &["abcdefghij"]).unwrap_err();
+        assert!(drift.renamed.is_empty());
+        assert_eq!(drift.missing, ["abcdefghij"]);
+        assert_eq!(drift.extra, ["abcdefwxyz"]);
+    }
+
+    #[test]
+    fn an_unexpected_header_is_only_used_for_one_rename() {
+        let drift = validate_headers(
+            "s",
+            &headers_of(&["Uebernachtung_Anzahl"]),
+            &["Uebernachtungen_Anzahl", "Uebernachtungen_Anzahl2"],
+        )
+        .unwrap_err();
+        assert_eq!(drift.renamed.len(), 1);
+        assert_eq!(drift.renamed[0].expected, "Uebernachtungen_Anzahl");
+        assert_eq!(drift.missing, ["Uebernachtungen_Anzahl2"]);
+        assert!(drift.extra.is_empty());
+    }
+}
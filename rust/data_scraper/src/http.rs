@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Seek, Write};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use reqwest::header::{RANGE, RETRY_AFTER};
+use reqwest::{IntoUrl, RequestBuilder, Response, StatusCode};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::Instant;
+
+use crate::metrics::METRICS;
+use crate::settings::{HostLimits, HttpSettings};
+
+/// The HTTP client every scraper goes through.
+///
+/// Requests are throttled per host: at most `max_concurrent_requests` at a time and
+/// at least `min_interval_ms` between two of them. Responses with 429 or 503 are
+/// retried after the `Retry-After` the server asked for.
+#[derive(Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    settings: Arc<HttpSettings>,
+    hosts: Arc<Mutex<HashMap<String, Arc<HostLimiter>>>>,
+}
+
+/// A response with an error status, kept as a type so callers can tell a missing
+/// resource from other failures.
+#[derive(Debug)]
+pub struct HttpStatusError {
+    pub url: String,
+    pub status: StatusCode,
+}
+
+impl std::fmt::Display for HttpStatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} answered with {}", self.url, self.status)
+    }
+}
+
+impl std::error::Error for HttpStatusError {}
+
+impl HttpStatusError {
+    /// Turns responses with a 4xx or 5xx status into an error.
+    pub fn check(response: HostResponse) -> Result<HostResponse, HttpStatusError> {
+        let status = response.status();
+        if status.is_client_error() || status.is_server_error() {
+            return Err(HttpStatusError {
+                url: response.url().to_string(),
+                status,
+            });
+        }
+        Ok(response)
+    }
+}
+
+/// A response that holds on to its host's permit until the body was read or the
+/// response is dropped, so body transfers count against `max_concurrent_requests` too.
+pub struct HostResponse {
+    response: Response,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl HostResponse {
+    pub async fn text(self) -> reqwest::Result<String> {
+        self.response.text().await
+    }
+}
+
+impl Deref for HostResponse {
+    type Target = Response;
+
+    fn deref(&self) -> &Self::Target {
+        &self.response
+    }
+}
+
+impl DerefMut for HostResponse {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.response
+    }
+}
+
+struct HostLimiter {
+    permits: Arc<Semaphore>,
+    min_interval: Duration,
+    /// Earliest time the next request to the host may start.
+    next_request: tokio::sync::Mutex<Instant>,
+}
+
+impl HttpClient {
+    pub fn new(settings: &HttpSettings) -> Result<Self, anyhow::Error> {
+        let mut builder = reqwest::Client::builder()
+            // Not a total timeout, that would also cut off large downloads.
+            .read_timeout(Duration::from_secs(settings.timeout_secs))
+            .connect_timeout(Duration::from_secs(settings.connect_timeout_secs))
+            .user_agent(&settings.user_agent)
+            .gzip(true)
+            .brotli(true);
+        if let Some(proxy) = &settings.proxy {
+            builder = builder.proxy(
+                reqwest::Proxy::all(proxy)
+                    .map_err(|e| anyhow::anyhow!("Invalid proxy {}: {}", proxy, e))?,
+            );
+        }
+
+        Ok(Self {
+            client: builder.build()?,
+            settings: Arc::new(settings.clone()),
+            hosts: Default::default(),
+        })
+    }
+
+    /// The underlying client, without any throttling. Only meant for our own
+    /// infrastructure, like the webhook notifier.
+    pub fn inner(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    pub fn get(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    /// Sends the request once the host's limits allow it. The response is returned
+    /// as is, status codes other than 429 and 503 are up to the caller. The host's
+    /// permit is only released once the response is dropped.
+    pub async fn send(&self, request: RequestBuilder) -> Result<HostResponse, anyhow::Error> {
+        let request = request.build()?;
+        let host = request.url().host_str().unwrap_or_default().to_string();
+        let limiter = self.limiter(&host);
+
+        let mut attempt = 0;
+        loop {
+            let Some(attempt_request) = request.try_clone() else {
+                return Err(anyhow::anyhow!(
+                    "Request to {} can't be retried because its body is a stream",
+                    request.url()
+                ));
+            };
+
+            let permit = limiter.permits.clone().acquire_owned().await?;
+            limiter.wait_for_turn().await;
+            let response = self.client.execute(attempt_request).await;
+            METRICS.observe_http_response(&response);
+            let response = HostResponse {
+                response: response
+                    .map_err(|e| anyhow::anyhow!("Request to {} failed: {}", request.url(), e))?,
+                _permit: permit,
+            };
+
+            let status = response.status();
+            if (status != StatusCode::TOO_MANY_REQUESTS
+                && status != StatusCode::SERVICE_UNAVAILABLE)
+                || attempt >= self.settings.max_retries
+            {
+                return Ok(response);
+            }
+
+            attempt += 1;
+            let delay = retry_after(&response).unwrap_or_else(|| backoff(attempt));
+            let max_delay = Duration::from_secs(self.settings.max_retry_after_secs);
+            if delay > max_delay {
+                warn!(
+                    "{} asked us to wait {:?} before retrying {}, which is longer than {:?}",
+                    host,
+                    delay,
+                    request.url(),
+                    max_delay
+                );
+                return Ok(response);
+            }
+
+            warn!(
+                "{} answered {} for {}, retrying in {:?} (attempt {}/{})",
+                host,
+                status,
+                request.url(),
+                delay,
+                attempt,
+                self.settings.max_retries
+            );
+            // Everybody else talking to this host has to back off as well.
+            limiter.delay_until(Instant::now() + delay).await;
+        }
+    }
+
+    /// Streams the body into the file chunk by chunk instead of buffering it. A transfer
+    /// that breaks off is resumed with a `Range` request where it stopped, or restarted
+    /// if the server doesn't support ranges. Returns the number of bytes in the file.
+    pub async fn download(
+        &self,
+        url: &str,
+        file: &mut File,
+        expected_size: Option<u64>,
+    ) -> Result<u64, anyhow::Error> {
+        let max_size = self.settings.max_download_size_mb * 1024 * 1024;
+        let limit = expected_size.unwrap_or(max_size);
+        if limit > max_size {
+            return Err(anyhow::anyhow!(
+                "{} has {} bytes, which is more than the limit of {} bytes",
+                url,
+                limit,
+                max_size
+            ));
+        }
+
+        let mut written = 0;
+        let mut attempt = 0;
+        loop {
+            let mut request = self.get(url);
+            if written > 0 {
+                request = request.header(RANGE, format!("bytes={}-", written));
+            }
+            let response = HttpStatusError::check(self.send(request).await?)?;
+
+            if written > 0 && response.status() != StatusCode::PARTIAL_CONTENT {
+                warn!("{} doesn't support ranges, restarting the download", url);
+                file.set_len(0)?;
+                file.rewind()?;
+                written = 0;
+            }
+            let total = response
+                .content_length()
+                .map(|x| x + written)
+                .or(expected_size);
+            if total.is_some_and(|x| x > limit) {
+                return Err(anyhow::anyhow!(
+                    "{} has {} bytes, which is more than the limit of {} bytes",
+                    url,
+                    total.unwrap_or_default(),
+                    limit
+                ));
+            }
+
+            match copy_body(response, file, &mut written, total, limit).await {
+                Ok(()) => {
+                    debug!("Downloaded {} bytes from {}", written, url);
+                    return Ok(written);
+                }
+                Err(DownloadError::Interrupted(e))
+                    if attempt < self.settings.max_resume_attempts =>
+                {
+                    attempt += 1;
+                    warn!(
+                        "Download of {} broke off after {} bytes, resuming (attempt {}/{}): {}",
+                        url, written, attempt, self.settings.max_resume_attempts, e
+                    );
+                }
+                Err(DownloadError::Interrupted(e)) => {
+                    return Err(anyhow::anyhow!(
+                        "Download of {} broke off after {} bytes: {}",
+                        url,
+                        written,
+                        e
+                    ))
+                }
+                Err(DownloadError::Fatal(e)) => {
+                    return Err(anyhow::anyhow!("Failed to download {}: {}", url, e))
+                }
+            }
+        }
+    }
+
+    fn limiter(&self, host: &str) -> Arc<HostLimiter> {
+        self.hosts
+            .lock()
+            .unwrap()
+            .entry(host.to_string())
+            .or_insert_with(|| {
+                let limits = self
+                    .settings
+                    .hosts
+                    .get(host)
+                    .unwrap_or(&self.settings.default_host_limits);
+                debug!("Limits for host {}: {:?}", host, limits);
+                Arc::new(HostLimiter::new(limits))
+            })
+            .clone()
+    }
+}
+
+impl HostLimiter {
+    fn new(limits: &HostLimits) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(limits.max_concurrent_requests)),
+            min_interval: Duration::from_millis(limits.min_interval_ms),
+            next_request: tokio::sync::Mutex::new(Instant::now()),
+        }
+    }
+
+    async fn wait_for_turn(&self) {
+        let mut next_request = self.next_request.lock().await;
+        tokio::time::sleep_until(*next_request).await;
+        *next_request = Instant::now() + self.min_interval;
+    }
+
+    async fn delay_until(&self, instant: Instant) {
+        let mut next_request = self.next_request.lock().await;
+        *next_request = (*next_request).max(instant);
+    }
+}
+
+enum DownloadError {
+    /// The connection failed mid-transfer, what we have so far is fine.
+    Interrupted(reqwest::Error),
+    Fatal(anyhow::Error),
+}
+
+/// Log progress every 10 percent, or every this many bytes if the size is unknown.
+const PROGRESS_INTERVAL_BYTES: u64 = 16 * 1024 * 1024;
+
+async fn copy_body(
+    mut response: HostResponse,
+    file: &mut File,
+    written: &mut u64,
+    total: Option<u64>,
+    limit: u64,
+) -> Result<(), DownloadError> {
+    let step = total.map_or(PROGRESS_INTERVAL_BYTES, |x| (x / 10).max(1));
+    let mut next_progress = *written + step;
+    while let Some(chunk) = response.chunk().await.map_err(DownloadError::Interrupted)? {
+        if *written + chunk.len() as u64 > limit {
+            return Err(DownloadError::Fatal(anyhow::anyhow!(
+                "More than the limit of {} bytes",
+                limit
+            )));
+        }
+        file.write_all(&chunk)
+            .map_err(|e| DownloadError::Fatal(anyhow::anyhow!("Failed to write: {}", e)))?;
+        *written += chunk.len() as u64;
+
+        if *written >= next_progress {
+            match total {
+                Some(total) => info!(
+                    "Downloaded {}% of {}",
+                    *written * 100 / total.max(1),
+                    response.url()
+                ),
+                None => info!("Downloaded {} bytes of {}", written, response.url()),
+            }
+            next_progress = *written + step;
+        }
+    }
+    Ok(())
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_secs(2u64.saturating_pow(attempt))
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+
+use log::{info, warn};
+use sqlx::pool::PoolConnection;
+use sqlx::Postgres;
+use tokio::sync::OwnedMutexGuard;
+
+use crate::context::Context;
+
+/// First half of the two-key advisory lock, so our locks don't collide with other
+/// applications sharing the database. It spells "BIP2" in ASCII.
+const ADVISORY_LOCK_NAMESPACE: i32 = 0x4249_5032;
+
+type LocalLocks = Mutex<HashMap<&'static str, Arc<tokio::sync::Mutex<()>>>>;
+
+static LOCAL_LOCKS: LazyLock<LocalLocks> = LazyLock::new(Default::default);
+
+/// Exclusive right to run a task, held until dropped or released.
+///
+/// Inside the process this is a mutex per task name. Across replicas it is a Postgres
+/// advisory lock, which Postgres also frees if the holding connection dies.
+pub struct TaskLock {
+    task_name: &'static str,
+    _local: OwnedMutexGuard<()>,
+    connection: Option<PoolConnection<Postgres>>,
+}
+
+impl TaskLock {
+    /// Returns `None` if the task is already running, either in this process or on
+    /// another replica.
+    pub async fn try_acquire(
+        context: &Context,
+        task_name: &'static str,
+    ) -> Result<Option<TaskLock>, anyhow::Error> {
+        let local = LOCAL_LOCKS
+            .lock()
+            .unwrap()
+            .entry(task_name)
+            .or_default()
+            .clone();
+        let Ok(local) = local.try_lock_owned() else {
+            info!("Task {} is already running in this process", task_name);
+            return Ok(None);
+        };
+
+        if !context.settings.advisory_locks_enabled {
+            return Ok(Some(TaskLock {
+                task_name,
+                _local: local,
+                connection: None,
+            }));
+        }
+
+        let mut connection = context.database_client.acquire().await?;
+        let acquired = sqlx::query_file_scalar!(
+            "src/queries/try_advisory_lock.sql",
+            ADVISORY_LOCK_NAMESPACE,
+            advisory_lock_key(task_name)
+        )
+        .fetch_one(&mut *connection)
+        .await?;
+
+        if !acquired {
+            info!(
+                "Advisory lock for task {} is held by another replica, skipping",
+                task_name
+            );
+            return Ok(None);
+        }
+
+        info!("Acquired advisory lock for task {}", task_name);
+        Ok(Some(TaskLock {
+            task_name,
+            _local: local,
+            connection: Some(connection),
+        }))
+    }
+
+    pub async fn release(mut self) {
+        let Some(mut connection) = self.connection.take() else {
+            return;
+        };
+
+        let released = sqlx::query_file_scalar!(
+            "src/queries/advisory_unlock.sql",
+            ADVISORY_LOCK_NAMESPACE,
+            advisory_lock_key(self.task_name)
+        )
+        .fetch_one(&mut *connection)
+        .await;
+
+        match released {
+            Ok(true) => info!("Released advisory lock for task {}", self.task_name),
+            Ok(false) => warn!(
+                "Advisory lock for task {} was not held on release",
+                self.task_name
+            ),
+            Err(e) => {
+                warn!(
+                    "Failed to release advisory lock for task {}, closing the connection instead: {}",
+                    self.task_name, e
+                );
+                // Closing the session is the other way to free an advisory lock.
+                drop(connection.detach());
+            }
+        }
+    }
+}
+
+impl Drop for TaskLock {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            warn!(
+                "Advisory lock for task {} dropped without release, closing the connection",
+                self.task_name
+            );
+            drop(connection.detach());
+        }
+    }
+}
+
+/// 32-bit FNV-1a. Unlike `DefaultHasher` this is stable across builds,
+/// which matters because every replica has to derive the same key.
+fn advisory_lock_key(task_name: &str) -> i32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for byte in task_name.bytes() {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash as i32
+}
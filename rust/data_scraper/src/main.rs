@@ -1,75 +1,147 @@
-use google_cloud_pubsub::client::{Client, ClientConfig};
-use log::{debug, error, info};
+use std::sync::Arc;
+
+use clap::{Parser, Subcommand};
+use log::{error, info, warn};
+use tokio::signal;
 use tokio::task::JoinSet;
-use tokio::{select, signal};
+use tokio_util::sync::CancellationToken;
 
 use crate::context::Context;
-use crate::tasks::{CronTask, CronTaskExtension};
+use crate::scheduler::Scheduler;
 
+mod archive;
+mod cache;
+mod consumer;
 mod context;
+mod events;
 mod helpers;
+mod http;
+mod locking;
+mod metrics;
+mod mods;
+mod mycore;
+mod notifier;
+mod outbox;
+mod quality;
+mod quarantine;
+mod reprocess;
+mod scheduler;
+mod server;
 mod settings;
 mod tasks;
+mod veraenderung;
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the scheduled tasks and the HTTP server. This is the default.
+    Run,
+    /// Listen for new data events and run the registered handlers.
+    Consume,
+    /// Parse the archived workbooks again and replace their months in the database.
+    /// Nothing is downloaded, the new data events are only written to the outbox.
+    Reprocess {
+        /// Only reprocess the workbook of this MODS id, e.g. `DEHeft_mods_00151234`.
+        #[arg(long)]
+        mods_id: Option<String>,
+    },
+    /// Recompute the changes to the previous year of every stored month from the
+    /// stored counts.
+    RecomputeVeraenderung,
+    /// Inspect the quarantined workbooks and rows, or retry their import.
+    Quarantine {
+        #[command(subcommand)]
+        command: quarantine::QuarantineCommand,
+    },
+}
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
     env_logger::init();
-    let settings = settings::Settings::new().expect("Unable to load settings");
-    let gcloud_config = ClientConfig::default().with_auth().await.unwrap();
-    let pub_sub_client = Client::new(gcloud_config).await.unwrap();
-
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(15))
-        .build()?;
+    let cli = Cli::parse();
+    let settings = settings::Settings::new().map_err(|e| anyhow::anyhow!("{}", e))?;
+    let client = http::HttpClient::new(&settings.http)?;
+    let archive = archive::ArtifactStore::new(&settings.archive)?;
+    let cache = cache::DownloadCache::new(&settings.cache, archive.clone())?;
 
-    let database = sqlx::PgPool::connect(&settings.database_url).await?;
+    let database = sqlx::PgPool::connect_with(settings.database_connect_options()?).await?;
     info!("Connected to database");
+    sqlx::migrate!().run(&database).await?;
+
+    let command = cli.command.unwrap_or(Command::Run);
+    let notifier: Arc<dyn notifier::Notifier> = match command {
+        // The outbox is dispatched by the next scheduler run instead.
+        Command::Reprocess { .. } | Command::Quarantine { .. } | Command::RecomputeVeraenderung => {
+            Arc::new(notifier::NoopNotifier)
+        }
+        _ => {
+            notifier::notifier_from_settings(&settings.notifier, &database, client.inner()).await?
+        }
+    };
 
     let context = Context {
         settings,
         http_client: client,
-        pubsub_client: pub_sub_client.clone(),
+        cache,
+        archive,
+        notifier,
         database_client: database,
+        cancellation: CancellationToken::new(),
     };
 
-    let tasks: Vec<Box<dyn CronTask>> = vec![
-        tasks::GetSleepoverDataTask.into_boxed(),
-        tasks::StartFerienUpdateTask.into_boxed(),
-    ];
+    let cancellation = context.cancellation.clone();
+    tokio::spawn(async move {
+        shutdown_signal_future().await;
+        cancellation.cancel();
+    });
+
+    match command {
+        Command::Run => run_scheduler(context).await,
+        Command::Consume => consumer::run(context).await,
+        Command::Reprocess { mods_id } => reprocess::run(context, mods_id.as_deref()).await,
+        Command::Quarantine { command } => quarantine::run(context, command).await,
+        Command::RecomputeVeraenderung => veraenderung::run(context).await,
+    }
+}
+
+async fn run_scheduler(context: Context) -> Result<(), anyhow::Error> {
+    let tasks = tasks::tasks_from_settings(&context.settings)?;
 
     let mut join_set = JoinSet::new();
-    for task in tasks {
-        info!("Starting task: {}", task.name());
-        let cloned_context = context.clone();
-        join_set.spawn(async move {
-            let task = task;
-            let mut interval = tokio::time::interval(task.interval());
-            let cancellation = shutdown_signal_future();
-            tokio::pin!(cancellation);
-
-            loop {
-                select! {
-                    _ = interval.tick() => {},
-                    _ = &mut cancellation => {
-                        info!("Task {} cancelled", task.name());
-                        break;
-                    }
-                }
-
-                async {
-                    if let Err(e) = task.run(&cloned_context).await {
-                        error!("Task {} failed: {}", task.name(), e);
-                    } else {
-                        debug!("Task {} completed", task.name());
-                    }
-                }
-                .await;
-            }
-        });
+    let scheduler = Scheduler::start(context.clone(), tasks, &mut join_set);
+
+    let server_context = context.clone();
+    let server_scheduler = scheduler.clone();
+    join_set.spawn(async move {
+        if let Err(e) = server::serve(server_context, server_scheduler).await {
+            error!("HTTP server failed: {}", e);
+        }
+    });
+
+    context.cancellation.cancelled().await;
+
+    let grace_period = std::time::Duration::from_secs(context.settings.shutdown_grace_period_secs);
+    info!(
+        "Waiting up to {:?} for running tasks to finish",
+        grace_period
+    );
+    let drained = tokio::time::timeout(grace_period, async {
+        while join_set.join_next().await.is_some() {}
+    })
+    .await;
+    if drained.is_err() {
+        warn!("Grace period elapsed, aborting remaining tasks");
+        join_set.abort_all();
+        while join_set.join_next().await.is_some() {}
     }
 
-    while join_set.join_next().await.is_some() {}
-
+    scheduler.log_shutdown_summary();
     info!("All tasks completed");
 
     Ok(())
@@ -94,6 +166,24 @@ fn translate_to_month(month: i32) -> String {
     .to_string()
 }
 
+fn translate_from_month(month: &str) -> Option<i32> {
+    Some(match month.trim() {
+        "Januar" => 1,
+        "Februar" => 2,
+        "März" => 3,
+        "April" => 4,
+        "Mai" => 5,
+        "Juni" => 6,
+        "Juli" => 7,
+        "August" => 8,
+        "September" => 9,
+        "Oktober" => 10,
+        "November" => 11,
+        "Dezember" => 12,
+        _ => return None,
+    })
+}
+
 async fn shutdown_signal_future() {
     let ctrl_c = async {
         signal::ctrl_c()
@@ -0,0 +1,158 @@
+use std::sync::LazyLock;
+
+use chrono::{NaiveDate, Utc};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests: IntCounterVec,
+    pub downloaded_bytes: IntCounterVec,
+    pub cache_lookups: IntCounterVec,
+    pub rows_parsed: IntCounterVec,
+    pub rows_inserted: IntCounterVec,
+    pub quality_violations: IntCounterVec,
+    pub task_duration: HistogramVec,
+    pub task_failures: IntCounterVec,
+    pub newest_data_month_age_days: IntGauge,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("data_scraper".to_string()), None)
+            /* Only fails for an invalid prefix, which is a constant. */
+            .unwrap();
+
+        let http_requests = IntCounterVec::new(
+            Opts::new(
+                "http_requests_total",
+                "HTTP requests sent, by host and status",
+            ),
+            &["host", "status"],
+        )
+        .unwrap();
+        let downloaded_bytes = IntCounterVec::new(
+            Opts::new("downloaded_bytes_total", "Bytes downloaded, by derivative"),
+            &["derivative"],
+        )
+        .unwrap();
+        let cache_lookups = IntCounterVec::new(
+            Opts::new(
+                "cache_lookups_total",
+                "Download cache lookups, by kind and result",
+            ),
+            &["kind", "result"],
+        )
+        .unwrap();
+        let rows_parsed = IntCounterVec::new(
+            Opts::new("rows_parsed_total", "Spreadsheet rows parsed, by sheet"),
+            &["sheet"],
+        )
+        .unwrap();
+        let rows_inserted = IntCounterVec::new(
+            Opts::new(
+                "rows_inserted_total",
+                "Rows inserted into the database, by table",
+            ),
+            &["table"],
+        )
+        .unwrap();
+        let quality_violations = IntCounterVec::new(
+            Opts::new(
+                "quality_violations_total",
+                "Data quality rule violations found, by rule",
+            ),
+            &["rule"],
+        )
+        .unwrap();
+        let task_duration = HistogramVec::new(
+            HistogramOpts::new("task_duration_seconds", "Duration of task runs, by task").buckets(
+                vec![
+                    0.5, 1.0, 5.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0, 3600.0,
+                ],
+            ),
+            &["task"],
+        )
+        .unwrap();
+        let task_failures = IntCounterVec::new(
+            Opts::new("task_failures_total", "Failed task runs, by task"),
+            &["task"],
+        )
+        .unwrap();
+        let newest_data_month_age_days = IntGauge::new(
+            "newest_data_month_age_days",
+            "Days since the start of the newest month present in the database",
+        )
+        .unwrap();
+
+        registry.register(Box::new(http_requests.clone())).unwrap();
+        registry
+            .register(Box::new(downloaded_bytes.clone()))
+            .unwrap();
+        registry.register(Box::new(cache_lookups.clone())).unwrap();
+        registry.register(Box::new(rows_parsed.clone())).unwrap();
+        registry.register(Box::new(rows_inserted.clone())).unwrap();
+        registry
+            .register(Box::new(quality_violations.clone()))
+            .unwrap();
+        registry.register(Box::new(task_duration.clone())).unwrap();
+        registry.register(Box::new(task_failures.clone())).unwrap();
+        registry
+            .register(Box::new(newest_data_month_age_days.clone()))
+            .unwrap();
+
+        Self {
+            registry,
+            http_requests,
+            downloaded_bytes,
+            cache_lookups,
+            rows_parsed,
+            rows_inserted,
+            quality_violations,
+            task_duration,
+            task_failures,
+            newest_data_month_age_days,
+        }
+    }
+
+    /// Counts a request by the host it was sent to and the resulting status code.
+    /// Requests that never got a response are counted with the status `error`.
+    pub fn observe_http_response(&self, response: &Result<reqwest::Response, reqwest::Error>) {
+        let (host, status) = match response {
+            Ok(response) => (
+                response.url().host_str().unwrap_or_default().to_string(),
+                response.status().as_u16().to_string(),
+            ),
+            Err(e) => (
+                e.url()
+                    .and_then(|x| x.host_str())
+                    .unwrap_or_default()
+                    .to_string(),
+                "error".to_string(),
+            ),
+        };
+        self.http_requests
+            .with_label_values(&[&host, &status])
+            .inc();
+    }
+
+    pub fn set_newest_data_month(&self, year: i32, month: u32) {
+        let Some(start_of_month) = NaiveDate::from_ymd_opt(year, month, 1) else {
+            return;
+        };
+        let today = Utc::now().date_naive();
+        self.newest_data_month_age_days
+            .set((today - start_of_month).num_days());
+    }
+
+    pub fn encode(&self) -> Result<String, anyhow::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(|e| anyhow::anyhow!("Failed to encode metrics: {}", e))?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
@@ -0,0 +1,288 @@
+//! Extracts the metadata we keep about a publication from its MODS record.
+
+use chrono::NaiveDate;
+use roxmltree::{Document, Node};
+use sqlx::PgPool;
+
+const MODS_NAMESPACE: &str = "http://www.loc.gov/mods/v3";
+const XLINK_NAMESPACE: &str = "http://www.w3.org/1999/xlink";
+
+#[derive(Debug, Clone)]
+pub struct Publication {
+    pub mods_id: String,
+    pub title: String,
+    pub reference_period_start: Option<NaiveDate>,
+    pub reference_period_end: Option<NaiveDate>,
+    pub published_on: Option<NaiveDate>,
+    /// Edition statement, e.g. that this is a corrected version of an earlier issue.
+    pub edition: Option<String>,
+    pub notes: Vec<String>,
+    pub license: Option<String>,
+}
+
+/// Parses the MyCoRe object XML, which wraps the MODS record.
+pub fn parse_publication(mods_id: &str, xml: &str) -> Result<Publication, anyhow::Error> {
+    let document =
+        Document::parse(xml).map_err(|e| anyhow::anyhow!("Invalid XML for {}: {}", mods_id, e))?;
+    let mods = document
+        .descendants()
+        .find(|x| x.has_tag_name((MODS_NAMESPACE, "mods")))
+        .ok_or(anyhow::anyhow!("No MODS record in {}", mods_id))?;
+
+    let title_info = child(mods, "titleInfo").ok_or(anyhow::anyhow!(
+        "No title in the MODS record of {}",
+        mods_id
+    ))?;
+    let mut title = child_text(title_info, "title").ok_or(anyhow::anyhow!(
+        "No title in the MODS record of {}",
+        mods_id
+    ))?;
+    if let Some(sub_title) = child_text(title_info, "subTitle") {
+        title = format!("{} - {}", title, sub_title);
+    }
+
+    let origin_info = children(mods, "originInfo").collect::<Vec<_>>();
+    let published_on = origin_info
+        .iter()
+        .flat_map(|x| children(*x, "dateIssued"))
+        .find_map(|x| parse_date(x.text()?, false));
+    let edition = origin_info.iter().find_map(|x| child_text(*x, "edition"));
+
+    // The reference period is a temporal subject, either a single point or a range.
+    // A single point only gives the start, the end is only taken from `point="end"`.
+    let temporal = children(mods, "subject")
+        .flat_map(|x| children(x, "temporal"))
+        .collect::<Vec<_>>();
+    let temporal_point = |point: &str| {
+        temporal
+            .iter()
+            .find(|x| x.attribute("point") == Some(point))
+            .and_then(|x| x.text())
+    };
+    let reference_period_start = temporal_point("start")
+        .or(temporal.first().and_then(|x| x.text()))
+        .and_then(|x| parse_date(x, false));
+    let reference_period_end = temporal_point("end").and_then(|x| parse_date(x, true));
+
+    let notes = children(mods, "note")
+        .filter_map(|x| text(x))
+        .collect::<Vec<_>>();
+
+    let license = children(mods, "accessCondition")
+        .find(|x| x.attribute("type") == Some("use and reproduction"))
+        .and_then(|x| {
+            text(x).or(x
+                .attribute((XLINK_NAMESPACE, "href"))
+                .map(|x| x.to_string()))
+        });
+
+    Ok(Publication {
+        mods_id: mods_id.to_string(),
+        title,
+        reference_period_start,
+        reference_period_end,
+        published_on,
+        edition,
+        notes,
+        license,
+    })
+}
+
+/// Inserts or updates the publication, keyed by its MODS id.
+pub async fn upsert_publication(
+    database: &PgPool,
+    series_id: &str,
+    publication: &Publication,
+) -> Result<(), anyhow::Error> {
+    sqlx::query_file!(
+        "src/queries/upsert_publication.sql",
+        publication.mods_id,
+        series_id,
+        publication.title,
+        publication.reference_period_start,
+        publication.reference_period_end,
+        publication.published_on,
+        publication.edition,
+        &publication.notes,
+        publication.license
+    )
+    .execute(database)
+    .await?;
+    Ok(())
+}
+
+fn children<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |x| x.has_tag_name((MODS_NAMESPACE, name)))
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &'static str) -> Option<Node<'a, 'input>> {
+    children(node, name).next()
+}
+
+fn child_text(node: Node, name: &'static str) -> Option<String> {
+    children(node, name).find_map(text)
+}
+
+/// Trimmed text content, `None` if there is none.
+fn text(node: Node) -> Option<String> {
+    let text = node
+        .descendants()
+        .filter(|x| x.is_text())
+        .filter_map(|x| x.text())
+        .collect::<String>();
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+/// Parses W3CDTF dates of any precision, `2024`, `2024-02` or `2024-02-15`.
+/// Incomplete dates become the first day of the period, or the last one if `end` is set.
+fn parse_date(value: &str, end: bool) -> Option<NaiveDate> {
+    let value = value.trim();
+    let mut parts = value.get(..10.min(value.len()))?.split('-');
+    let year = parts.next()?.parse::<i32>().ok()?;
+    let month = parts.next().map(|x| x.parse::<u32>()).transpose().ok()?;
+    let day = parts.next().map(|x| x.parse::<u32>()).transpose().ok()?;
+
+    match (month, day, end) {
+        (Some(month), Some(day), _) => NaiveDate::from_ymd_opt(year, month, day),
+        (Some(month), None, false) => NaiveDate::from_ymd_opt(year, month, 1),
+        (Some(month), None, true) => {
+            let next_month = if month == 12 {
+                NaiveDate::from_ymd_opt(year + 1, 1, 1)
+            } else {
+                NaiveDate::from_ymd_opt(year, month + 1, 1)
+            };
+            next_month?.pred_opt()
+        }
+        (None, _, false) => NaiveDate::from_ymd_opt(year, 1, 1),
+        (None, _, true) => NaiveDate::from_ymd_opt(year, 12, 31),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(year, month, day)
+    }
+
+    fn record(mods: &str) -> String {
+        format!(
+            r#"<mycoreobject ID="DEHeBIB_mods_00000001">
+  <metadata>
+    <def.modsContainer>
+      <modsContainer>
+        <mods:mods xmlns:mods="{}" xmlns:xlink="{}">
+          {}
+        </mods:mods>
+      </modsContainer>
+    </def.modsContainer>
+  </metadata>
+</mycoreobject>"#,
+            MODS_NAMESPACE, XLINK_NAMESPACE, mods
+        )
+    }
+
+    #[test]
+    fn parses_dates_of_any_precision() {
+        assert_eq!(parse_date("2024-02-15", false), date(2024, 2, 15));
+        assert_eq!(parse_date("2024-02-15", true), date(2024, 2, 15));
+        assert_eq!(parse_date("2024-02", false), date(2024, 2, 1));
+        assert_eq!(parse_date("2024-02", true), date(2024, 2, 29));
+        assert_eq!(parse_date("2023-12", true), date(2023, 12, 31));
+        assert_eq!(parse_date(" 2024 ", false), date(2024, 1, 1));
+        assert_eq!(parse_date("2024", true), date(2024, 12, 31));
+        assert_eq!(parse_date("2024-02-15T10:00:00Z", false), date(2024, 2, 15));
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        assert_eq!(parse_date("", false), None);
+        assert_eq!(parse_date("Februar 2024", false), None);
+        assert_eq!(parse_date("2024-13", false), None);
+        assert_eq!(parse_date("2023-02-30", false), None);
+    }
+
+    #[test]
+    fn parses_a_publication() {
+        let xml = record(
+            r#"<mods:titleInfo>
+            <mods:title>Gäste und Übernachtungen  in Hessen</mods:title>
+            <mods:subTitle>Februar 2024</mods:subTitle>
+          </mods:titleInfo>
+          <mods:originInfo eventType="publication">
+            <mods:dateIssued encoding="w3cdtf">2024-04-18</mods:dateIssued>
+            <mods:edition>Korrigierte Fassung</mods:edition>
+          </mods:originInfo>
+          <mods:subject>
+            <mods:temporal encoding="w3cdtf" point="start">2024-02</mods:temporal>
+            <mods:temporal encoding="w3cdtf" point="end">2024-02</mods:temporal>
+          </mods:subject>
+          <mods:note>Vorläufige Ergebnisse</mods:note>
+          <mods:note> </mods:note>
+          <mods:accessCondition type="use and reproduction"
+            xlink:href="https://creativecommons.org/licenses/by/4.0/" />"#,
+        );
+
+        let publication = parse_publication("DEHeBIB_mods_00000001", &xml).unwrap();
+        assert_eq!(publication.mods_id, "DEHeBIB_mods_00000001");
+        assert_eq!(
+            publication.title,
+            "Gäste und Übernachtungen in Hessen - Februar 2024"
+        );
+        assert_eq!(publication.published_on, date(2024, 4, 18));
+        assert_eq!(publication.edition.as_deref(), Some("Korrigierte Fassung"));
+        assert_eq!(publication.reference_period_start, date(2024, 2, 1));
+        assert_eq!(publication.reference_period_end, date(2024, 2, 29));
+        assert_eq!(publication.notes, ["Vorläufige Ergebnisse"]);
+        assert_eq!(
+            publication.license.as_deref(),
+            Some("https://creativecommons.org/licenses/by/4.0/")
+        );
+    }
+
+    #[test]
+    fn a_start_date_alone_has_no_end() {
+        let xml = record(
+            r#"<mods:titleInfo><mods:title>Monatsbericht</mods:title></mods:titleInfo>
+          <mods:subject>
+            <mods:temporal encoding="w3cdtf" point="start">2024-02</mods:temporal>
+          </mods:subject>"#,
+        );
+
+        let publication = parse_publication("DEHeBIB_mods_00000001", &xml).unwrap();
+        assert_eq!(publication.reference_period_start, date(2024, 2, 1));
+        assert_eq!(publication.reference_period_end, None);
+        assert_eq!(publication.published_on, None);
+        assert_eq!(publication.license, None);
+    }
+
+    #[test]
+    fn a_single_point_is_the_start() {
+        let xml = record(
+            r#"<mods:titleInfo><mods:title>Monatsbericht</mods:title></mods:titleInfo>
+          <mods:subject><mods:temporal encoding="w3cdtf">2024</mods:temporal></mods:subject>"#,
+        );
+
+        let publication = parse_publication("DEHeBIB_mods_00000001", &xml).unwrap();
+        assert_eq!(publication.reference_period_start, date(2024, 1, 1));
+        assert_eq!(publication.reference_period_end, None);
+    }
+
+    #[test]
+    fn fails_without_a_title() {
+        let xml = record("<mods:note>Ohne Titel</mods:note>");
+        let error = parse_publication("DEHeBIB_mods_00000001", &xml).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "No title in the MODS record of DEHeBIB_mods_00000001"
+        );
+        assert!(parse_publication("DEHeBIB_mods_00000001", "<mycoreobject>").is_err());
+        assert!(parse_publication("DEHeBIB_mods_00000001", "<mycoreobject/>").is_err());
+    }
+}
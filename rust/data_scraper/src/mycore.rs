@@ -0,0 +1,446 @@
+//! Client for the MyCoRe v1 REST API of statistischebibliothek.de.
+//!
+//! A publication (`DEHeft_mods_*`) is a MyCoRe object with MODS metadata. Its files
+//! live in one or more derivates, each of which has a list of contents.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+
+use log::{debug, warn};
+use reqwest::{StatusCode, Url};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::archive::ArtifactOrigin;
+use crate::context::Context;
+use crate::http::HttpStatusError;
+
+/// Editions are requested in pages of this size from the search.
+const SEARCH_PAGE_SIZE: usize = 100;
+/// Guards against a search that never stops returning documents.
+const MAX_SEARCH_PAGES: usize = 100;
+
+/// Fields that may hold the "2024,02" label of an edition, in order of preference.
+const EDITION_LABEL_FIELDS: [&str; 3] = ["mods.title.main", "mods.title", "mods.dateIssued"];
+
+#[derive(Debug)]
+pub enum MyCoReError {
+    /// The object, derivate or file doesn't exist (anymore).
+    NotFound {
+        url: String,
+    },
+    /// The API answered, but not in the shape we expect. Most likely it changed.
+    SchemaDrift {
+        url: String,
+        error: String,
+    },
+    /// No file of a derivate matches the wanted extensions.
+    NoMatchingFile {
+        object_id: String,
+        wanted: Vec<String>,
+    },
+    Request(anyhow::Error),
+}
+
+impl Display for MyCoReError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MyCoReError::NotFound { url } => write!(f, "{} not found", url),
+            MyCoReError::SchemaDrift { url, error } => {
+                write!(f, "Unexpected response from {}: {}", url, error)
+            }
+            MyCoReError::NoMatchingFile { object_id, wanted } => write!(
+                f,
+                "{} has no file with one of the extensions {}",
+                object_id,
+                wanted.join(", ")
+            ),
+            MyCoReError::Request(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for MyCoReError {}
+
+impl From<anyhow::Error> for MyCoReError {
+    fn from(value: anyhow::Error) -> Self {
+        match value.downcast_ref::<HttpStatusError>() {
+            Some(e) if e.status == StatusCode::NOT_FOUND => {
+                MyCoReError::NotFound { url: e.url.clone() }
+            }
+            _ => MyCoReError::Request(value),
+        }
+    }
+}
+
+/// Response of `/objects/{id}/derivates`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DerivatesResponse {
+    #[serde(rename = "numFound")]
+    pub num_found: i32,
+    pub mycoreobjects: Vec<CoreObject>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CoreObject {
+    #[serde(rename = "ID")]
+    pub id: String,
+    pub metadata: String,
+    pub label: String,
+    #[serde(rename = "lastModified")]
+    pub last_modified: chrono::DateTime<chrono::Utc>,
+    pub href: String,
+}
+
+/// Response of `{derivate}/contents`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContentsResponse {
+    // There are more fields here, but we only care about the children.
+    pub children: Vec<Derivative>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Derivative {
+    #[serde(rename = "type")]
+    pub r#type: String,
+    pub name: String,
+    pub path: String,
+    #[serde(rename = "parentPath")]
+    pub parent_path: String,
+    pub size: usize,
+    // Ignore The Time Field.
+    #[serde(rename = "contentType")]
+    pub content_type: String,
+    pub md5: String,
+    pub extension: String,
+    pub href: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchResponse {
+    pub response: SearchResult,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchResult {
+    #[serde(rename = "numFound")]
+    pub num_found: usize,
+    pub docs: Vec<SearchDocument>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchDocument {
+    pub id: String,
+    #[serde(flatten)]
+    pub fields: HashMap<String, serde_json::Value>,
+}
+
+impl SearchDocument {
+    /// The first value of the field, whether Solr returned it as a list or not.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        match self.fields.get(name)? {
+            serde_json::Value::String(value) => Some(value.as_str()),
+            serde_json::Value::Array(values) => values.first()?.as_str(),
+            _ => None,
+        }
+    }
+}
+
+/// An edition of a series as listed by the search, before its label is parsed.
+#[derive(Debug)]
+pub struct SeriesEdition {
+    pub mods_id: String,
+    pub label: Option<String>,
+}
+
+/// Borrows the HTTP client and cache of the context, so it is cheap to create.
+pub struct MyCoReClient<'a> {
+    context: &'a Context,
+    base_url: &'a str,
+}
+
+impl<'a> MyCoReClient<'a> {
+    pub fn new(context: &'a Context) -> Self {
+        Self {
+            context,
+            base_url: &context.settings.sources.statistische_bibliothek_url,
+        }
+    }
+
+    /// Url of the HTML page of an object, e.g. a series.
+    pub fn receive_url(&self, id: &str) -> String {
+        format!("{}/mir/receive/{}", self.base_url, id)
+    }
+
+    /// Fetches a page through the cache.
+    pub async fn page(&self, url: &str) -> Result<String, MyCoReError> {
+        Ok(self
+            .context
+            .cache
+            .page(&self.context.http_client, url)
+            .await?)
+    }
+
+    async fn json<T: DeserializeOwned>(&self, url: &str) -> Result<T, MyCoReError> {
+        let body = self.page(url).await?;
+        serde_json::from_str(&body).map_err(|e| MyCoReError::SchemaDrift {
+            url: url.to_string(),
+            error: e.to_string(),
+        })
+    }
+
+    /// The object itself as XML, for publications this contains the MODS metadata.
+    pub async fn object(&self, id: &str) -> Result<String, MyCoReError> {
+        self.page(&format!("{}/mir/api/v1/objects/{}", self.base_url, id))
+            .await
+    }
+
+    pub async fn derivates(&self, id: &str) -> Result<Vec<CoreObject>, MyCoReError> {
+        let response: DerivatesResponse = self
+            .json(&format!(
+                "{}/mir/api/v1/objects/{}/derivates?format=json",
+                self.base_url, id
+            ))
+            .await?;
+        Ok(response.mycoreobjects)
+    }
+
+    pub async fn contents(&self, derivate: &CoreObject) -> Result<Vec<Derivative>, MyCoReError> {
+        let response: ContentsResponse = self
+            .json(&format!("{}/contents?format=json", derivate.href))
+            .await?;
+        Ok(response.children)
+    }
+
+    /// Runs a Solr query and returns one page of results.
+    pub async fn search(
+        &self,
+        query: &str,
+        fields: &[&str],
+        start: usize,
+        rows: usize,
+    ) -> Result<SearchResult, MyCoReError> {
+        let mut url = Url::parse(&format!("{}/mir/servlets/solr/select", self.base_url))
+            .map_err(|e| anyhow::anyhow!("Invalid search url for {}: {}", self.base_url, e))?;
+        url.query_pairs_mut()
+            .append_pair("q", query)
+            .append_pair("fl", &fields.join(","))
+            .append_pair("sort", "id asc")
+            .append_pair("start", &start.to_string())
+            .append_pair("rows", &rows.to_string())
+            .append_pair("wt", "json");
+
+        let response: SearchResponse = self.json(url.as_str()).await?;
+        Ok(response.response)
+    }
+
+    /// Lists every edition of a series, page by page.
+    pub async fn series_editions(
+        &self,
+        series_id: &str,
+    ) -> Result<Vec<SeriesEdition>, MyCoReError> {
+        let mut fields = vec!["id"];
+        fields.extend(EDITION_LABEL_FIELDS);
+
+        let mut editions = Vec::new();
+        for page in 0..MAX_SEARCH_PAGES {
+            let start = page * SEARCH_PAGE_SIZE;
+            let result = self
+                .search(
+                    &format!("parent:{}", series_id),
+                    &fields,
+                    start,
+                    SEARCH_PAGE_SIZE,
+                )
+                .await?;
+
+            let received = result.docs.len();
+            editions.extend(result.docs.into_iter().map(|x| {
+                SeriesEdition {
+                    label: EDITION_LABEL_FIELDS
+                        .iter()
+                        .find_map(|field| x.field(field))
+                        .map(|x| x.to_string()),
+                    mods_id: x.id,
+                }
+            }));
+            if received == 0 || start + received >= result.num_found {
+                return Ok(editions);
+            }
+        }
+
+        Err(MyCoReError::Request(anyhow::anyhow!(
+            "Search for series {} returned more than {} pages",
+            series_id,
+            MAX_SEARCH_PAGES
+        )))
+    }
+
+    /// Finds the file of a publication with the most preferred extension, looking
+    /// through all of its derivates.
+    pub async fn find_file(
+        &self,
+        object_id: &str,
+        extensions: &[String],
+    ) -> Result<Derivative, MyCoReError> {
+        let mut candidates = Vec::new();
+        for derivate in self.derivates(object_id).await? {
+            candidates.extend(self.contents(&derivate).await?);
+        }
+        debug!("{} has {} file(s)", object_id, candidates.len());
+
+        select_file(candidates, extensions).ok_or_else(|| MyCoReError::NoMatchingFile {
+            object_id: object_id.to_string(),
+            wanted: extensions.to_vec(),
+        })
+    }
+
+    pub async fn download(
+        &self,
+        file: &Derivative,
+        origin: ArtifactOrigin,
+    ) -> Result<File, MyCoReError> {
+        Ok(self
+            .context
+            .cache
+            .derivative(&self.context.http_client, file, origin)
+            .await?)
+    }
+}
+
+/// Picks the file with the earliest extension in `extensions`, judged by the
+/// extension or, if that is missing, the content type.
+fn select_file(candidates: Vec<Derivative>, extensions: &[String]) -> Option<Derivative> {
+    let rank = |file: &Derivative| {
+        if file.r#type != "file" {
+            return None;
+        }
+        let extension = if file.extension.is_empty() {
+            extension_for_content_type(&file.content_type)?
+        } else {
+            file.extension.to_lowercase()
+        };
+        extensions
+            .iter()
+            .position(|x| x.eq_ignore_ascii_case(&extension))
+    };
+
+    let mut ranked = candidates
+        .into_iter()
+        .filter_map(|x| Some((rank(&x)?, x)))
+        .collect::<Vec<_>>();
+    ranked.sort_by_key(|(rank, _)| *rank);
+
+    let best = ranked.first().map(|(rank, _)| *rank)?;
+    if ranked.iter().filter(|(rank, _)| *rank == best).count() > 1 {
+        warn!(
+            "Found several .{} files, using {}",
+            extensions[best], ranked[0].1.name
+        );
+    }
+    Some(ranked.swap_remove(0).1)
+}
+
+fn extension_for_content_type(content_type: &str) -> Option<String> {
+    let content_type = content_type.split(';').next()?.trim();
+    Some(
+        match content_type {
+            "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" => "xlsx",
+            "application/vnd.ms-excel" => "xls",
+            "application/pdf" => "pdf",
+            "text/csv" => "csv",
+            "application/zip" => "zip",
+            _ => return None,
+        }
+        .to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, extension: &str, content_type: &str) -> Derivative {
+        Derivative {
+            r#type: "file".to_string(),
+            name: name.to_string(),
+            path: format!("/{}", name),
+            parent_path: "/".to_string(),
+            size: 1024,
+            content_type: content_type.to_string(),
+            md5: "d41d8cd98f00b204e9800998ecf8427e".to_string(),
+            extension: extension.to_string(),
+            href: format!("https://example.org/derivate/{}", name),
+        }
+    }
+
+    fn wanted(extensions: &[&str]) -> Vec<String> {
+        extensions.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn maps_content_types() {
+        assert_eq!(
+            extension_for_content_type(
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+            )
+            .as_deref(),
+            Some("xlsx")
+        );
+        assert_eq!(
+            extension_for_content_type("application/vnd.ms-excel; charset=binary").as_deref(),
+            Some("xls")
+        );
+        assert_eq!(
+            extension_for_content_type("text/csv;charset=UTF-8").as_deref(),
+            Some("csv")
+        );
+        assert_eq!(extension_for_content_type("text/html"), None);
+        assert_eq!(extension_for_content_type(""), None);
+    }
+
+    #[test]
+    fn selects_the_most_preferred_extension() {
+        let candidates = vec![
+            file("bericht.pdf", "pdf", "application/pdf"),
+            file("bericht.xls", "xls", "application/vnd.ms-excel"),
+            file("bericht.XLSX", "XLSX", ""),
+        ];
+        let selected = select_file(candidates, &wanted(&["xlsx", "xls"])).unwrap();
+        assert_eq!(selected.name, "bericht.XLSX");
+    }
+
+    #[test]
+    fn falls_back_to_the_content_type() {
+        let candidates = vec![
+            file("bericht.pdf", "pdf", "application/pdf"),
+            file("bericht", "", "application/vnd.ms-excel"),
+        ];
+        let selected = select_file(candidates, &wanted(&["xlsx", "xls"])).unwrap();
+        assert_eq!(selected.name, "bericht");
+    }
+
+    #[test]
+    fn takes_the_first_of_equally_ranked_files() {
+        let candidates = vec![
+            file("bericht.xlsx", "xlsx", ""),
+            file("anhang.xlsx", "xlsx", ""),
+        ];
+        let selected = select_file(candidates, &wanted(&["xlsx"])).unwrap();
+        assert_eq!(selected.name, "bericht.xlsx");
+    }
+
+    #[test]
+    fn ignores_directories_and_unwanted_files() {
+        let mut directory = file("tabellen.xlsx", "xlsx", "");
+        directory.r#type = "directory".to_string();
+        let candidates = vec![
+            directory,
+            file("bericht.pdf", "pdf", "application/pdf"),
+            file("bericht", "", "text/html"),
+        ];
+        assert!(select_file(candidates, &wanted(&["xlsx", "xls"])).is_none());
+        assert!(select_file(Vec::new(), &wanted(&["xlsx"])).is_none());
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use google_cloud_gax::conn::Environment;
+use google_cloud_googleapis::pubsub::v1::PubsubMessage;
+use google_cloud_pubsub::client::{Client, ClientConfig};
+use google_cloud_pubsub::publisher::Publisher;
+use tokio::sync::Mutex;
+
+use crate::notifier::Notifier;
+use crate::settings::NotifierSettings;
+
+pub struct GooglePubSubNotifier {
+    client: Client,
+    publishers: Mutex<HashMap<String, Publisher>>,
+}
+
+/// Creates a Pub/Sub client for either Google Cloud or the local emulator.
+pub async fn connect_pub_sub(settings: &NotifierSettings) -> Result<Client, anyhow::Error> {
+    let config = match settings {
+        // Uses the application default credentials.
+        NotifierSettings::GooglePubSub => ClientConfig::default()
+            .with_auth()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to authenticate with Google Cloud: {}", e))?,
+        NotifierSettings::PubSubEmulator { host, project_id } => ClientConfig {
+            project_id: Some(project_id.to_string()),
+            environment: Environment::Emulator(host.to_string()),
+            ..Default::default()
+        },
+        _ => return Err(anyhow::anyhow!("{:?} is not a Pub/Sub notifier", settings)),
+    };
+
+    Client::new(config)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to create Pub/Sub client: {}", e))
+}
+
+impl GooglePubSubNotifier {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            publishers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn publisher(&self, topic: &str) -> Result<Publisher, anyhow::Error> {
+        let mut publishers = self.publishers.lock().await;
+        if let Some(publisher) = publishers.get(topic) {
+            return Ok(publisher.clone());
+        }
+
+        let pub_sub_topic = self.client.topic(topic);
+        if !pub_sub_topic.exists(None).await? {
+            pub_sub_topic.create(None, None).await?;
+        }
+        let publisher = pub_sub_topic.new_publisher(None);
+        publishers.insert(topic.to_string(), publisher.clone());
+        Ok(publisher)
+    }
+}
+
+#[async_trait]
+impl Notifier for GooglePubSubNotifier {
+    fn name(&self) -> &'static str {
+        "GooglePubSub"
+    }
+
+    async fn publish(
+        &self,
+        topic: &str,
+        payload: &[u8],
+        attributes: &HashMap<String, String>,
+    ) -> Result<(), anyhow::Error> {
+        let publisher = self.publisher(topic).await?;
+        let awaiter = publisher
+            .publish(PubsubMessage {
+                data: payload.to_vec(),
+                attributes: attributes.clone(),
+                ..Default::default()
+            })
+            .await;
+        awaiter.get().await?;
+        Ok(())
+    }
+}
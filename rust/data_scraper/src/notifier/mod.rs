@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use log::info;
+
+pub use google_pubsub::{connect_pub_sub, GooglePubSubNotifier};
+pub use nats::NatsNotifier;
+pub use noop::NoopNotifier;
+pub use postgres::PostgresNotifier;
+pub use redis::RedisNotifier;
+pub use webhook::WebhookNotifier;
+
+use crate::settings::NotifierSettings;
+
+mod google_pubsub;
+mod nats;
+mod noop;
+mod postgres;
+mod redis;
+mod webhook;
+
+/// Publishes notifications for downstream consumers, e.g. when new data was added.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Backends without message attributes drop them, the payload has to be self-contained.
+    async fn publish(
+        &self,
+        topic: &str,
+        payload: &[u8],
+        attributes: &HashMap<String, String>,
+    ) -> Result<(), anyhow::Error>;
+}
+
+pub async fn notifier_from_settings(
+    settings: &NotifierSettings,
+    database_client: &sqlx::PgPool,
+    http_client: &reqwest::Client,
+) -> Result<Arc<dyn Notifier>, anyhow::Error> {
+    let notifier: Arc<dyn Notifier> = match settings {
+        NotifierSettings::GooglePubSub | NotifierSettings::PubSubEmulator { .. } => {
+            Arc::new(GooglePubSubNotifier::new(connect_pub_sub(settings).await?))
+        }
+        NotifierSettings::Nats { url } => Arc::new(NatsNotifier::new(url).await?),
+        NotifierSettings::Redis { url } => Arc::new(RedisNotifier::new(url).await?),
+        NotifierSettings::PostgresNotify => {
+            Arc::new(PostgresNotifier::new(database_client.clone()))
+        }
+        NotifierSettings::Webhook { url, bearer_token } => Arc::new(WebhookNotifier::new(
+            http_client.clone(),
+            url.clone(),
+            bearer_token.clone(),
+        )),
+        NotifierSettings::None => Arc::new(NoopNotifier),
+    };
+    info!("Using notifier: {}", notifier.name());
+    Ok(notifier)
+}
@@ -0,0 +1,43 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::notifier::Notifier;
+
+pub struct NatsNotifier {
+    client: async_nats::Client,
+}
+
+impl NatsNotifier {
+    pub async fn new(url: &str) -> Result<Self, anyhow::Error> {
+        let client = async_nats::connect(url)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to connect to NATS at {}: {}", url, e))?;
+        Ok(Self { client })
+    }
+}
+
+#[async_trait]
+impl Notifier for NatsNotifier {
+    fn name(&self) -> &'static str {
+        "Nats"
+    }
+
+    async fn publish(
+        &self,
+        topic: &str,
+        payload: &[u8],
+        attributes: &HashMap<String, String>,
+    ) -> Result<(), anyhow::Error> {
+        let mut headers = async_nats::HeaderMap::new();
+        for (key, value) in attributes {
+            headers.insert(key.as_str(), value.as_str());
+        }
+        self.client
+            .publish_with_headers(topic.to_string(), headers, payload.to_vec().into())
+            .await?;
+        // Publishing only buffers the message, flushing makes sure it reached the server.
+        self.client.flush().await?;
+        Ok(())
+    }
+}
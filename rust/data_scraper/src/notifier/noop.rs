@@ -0,0 +1,31 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use log::info;
+
+use crate::notifier::Notifier;
+
+/// Only logs notifications, for running without any message bus.
+pub struct NoopNotifier;
+
+#[async_trait]
+impl Notifier for NoopNotifier {
+    fn name(&self) -> &'static str {
+        "None"
+    }
+
+    async fn publish(
+        &self,
+        topic: &str,
+        payload: &[u8],
+        attributes: &HashMap<String, String>,
+    ) -> Result<(), anyhow::Error> {
+        info!(
+            "Not publishing {} bytes to {} ({:?}), notifications are disabled",
+            payload.len(),
+            topic,
+            attributes
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::notifier::Notifier;
+
+/// Sends notifications with `NOTIFY`, consumers subscribe with `LISTEN <topic>`.
+pub struct PostgresNotifier {
+    database_client: sqlx::PgPool,
+}
+
+impl PostgresNotifier {
+    pub fn new(database_client: sqlx::PgPool) -> Self {
+        Self { database_client }
+    }
+}
+
+#[async_trait]
+impl Notifier for PostgresNotifier {
+    fn name(&self) -> &'static str {
+        "PostgresNotify"
+    }
+
+    async fn publish(
+        &self,
+        topic: &str,
+        payload: &[u8],
+        _attributes: &HashMap<String, String>,
+    ) -> Result<(), anyhow::Error> {
+        // NOTIFY payloads are text, so the payload has to be valid UTF-8.
+        let payload = std::str::from_utf8(payload)
+            .map_err(|e| anyhow::anyhow!("Postgres notifications must be UTF-8: {}", e))?;
+        sqlx::query_file!("src/queries/notify.sql", topic, payload)
+            .execute(&self.database_client)
+            .await?;
+        Ok(())
+    }
+}
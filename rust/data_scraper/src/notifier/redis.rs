@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use redis::aio::MultiplexedConnection;
+
+use crate::notifier::Notifier;
+
+pub struct RedisNotifier {
+    connection: MultiplexedConnection,
+}
+
+impl RedisNotifier {
+    pub async fn new(url: &str) -> Result<Self, anyhow::Error> {
+        let client = redis::Client::open(url)
+            .map_err(|e| anyhow::anyhow!("Invalid redis url {}: {}", url, e))?;
+        let connection = client
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to connect to redis at {}: {}", url, e))?;
+        Ok(Self { connection })
+    }
+}
+
+#[async_trait]
+impl Notifier for RedisNotifier {
+    fn name(&self) -> &'static str {
+        "Redis"
+    }
+
+    async fn publish(
+        &self,
+        topic: &str,
+        payload: &[u8],
+        _attributes: &HashMap<String, String>,
+    ) -> Result<(), anyhow::Error> {
+        // The multiplexed connection is meant to be cloned for every request.
+        let mut connection = self.connection.clone();
+        redis::cmd("PUBLISH")
+            .arg(topic)
+            .arg(payload)
+            .query_async::<_, i64>(&mut connection)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use reqwest::Client;
+
+use crate::metrics::METRICS;
+use crate::notifier::Notifier;
+
+/// POSTs the payload to a fixed url. The topic is sent in the `X-Topic` header and every
+/// attribute as an `X-Attribute-<key>` header.
+pub struct WebhookNotifier {
+    client: Client,
+    url: String,
+    bearer_token: Option<String>,
+}
+
+impl WebhookNotifier {
+    pub fn new(client: Client, url: String, bearer_token: Option<String>) -> Self {
+        Self {
+            client,
+            url,
+            bearer_token,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &'static str {
+        "Webhook"
+    }
+
+    async fn publish(
+        &self,
+        topic: &str,
+        payload: &[u8],
+        attributes: &HashMap<String, String>,
+    ) -> Result<(), anyhow::Error> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header("X-Topic", topic)
+            .header("Content-Type", "application/octet-stream")
+            .body(payload.to_vec());
+        for (key, value) in attributes {
+            request = request.header(format!("X-Attribute-{}", key), value);
+        }
+        if let Some(token) = &self.bearer_token {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request.send().await;
+        METRICS.observe_http_response(&response);
+        response
+            .map_err(|e| anyhow::anyhow!("Failed to call webhook {}: {}", self.url, e))?
+            .error_for_status()
+            .map_err(|e| {
+                anyhow::anyhow!("Webhook {} rejected the notification: {}", self.url, e)
+            })?;
+        Ok(())
+    }
+}
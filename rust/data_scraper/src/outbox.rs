@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use log::{error, info, warn};
+use sqlx::PgConnection;
+
+use crate::context::Context;
+use crate::events::NewDataEvent;
+
+/// Stores an event for delivery. Call this inside the transaction that writes the data the
+/// event is about, so either both or neither end up in the database.
+pub async fn enqueue_event(
+    connection: &mut PgConnection,
+    topic: &str,
+    event: &NewDataEvent,
+) -> Result<(), anyhow::Error> {
+    let attributes = serde_json::to_value(event.attributes())?;
+    sqlx::query_file!(
+        "src/queries/insert_outbox_event.sql",
+        topic,
+        event.to_json()?,
+        attributes
+    )
+    .execute(connection)
+    .await?;
+    Ok(())
+}
+
+/// Claims are released once an event was published or failed. If a run dies before, the
+/// events become pending again after this many seconds.
+const CLAIM_TIMEOUT_SECONDS: f64 = 600.0;
+
+/// Publishes pending events in the order they were written and returns how many were sent.
+///
+/// A batch is claimed and committed first, so neither row locks nor a transaction are held
+/// while publishing. An event is only marked as dispatched after the notifier accepted it,
+/// so a crash in between sends it again. Nothing deduplicates them, consumers have to be
+/// idempotent.
+///
+/// An event that failed `max_attempts` times, or can't ever be published, is parked and
+/// no longer holds up the events after it.
+pub async fn dispatch_pending(
+    context: &Context,
+    batch_size: i64,
+    max_attempts: i32,
+) -> Result<usize, anyhow::Error> {
+    let mut pending = sqlx::query_file!(
+        "src/queries/claim_outbox_events.sql",
+        batch_size,
+        CLAIM_TIMEOUT_SECONDS
+    )
+    .fetch_all(&context.database_client)
+    .await?;
+    pending.sort_by_key(|x| x.id);
+
+    let mut dispatched = 0;
+    let mut pending = pending.into_iter();
+    while let Some(event) = pending.next() {
+        let (error, permanent) =
+            match serde_json::from_value::<HashMap<String, String>>(event.attributes) {
+                Ok(attributes) => match context
+                    .notifier
+                    .publish(&event.topic, &event.payload, &attributes)
+                    .await
+                {
+                    Ok(()) => {
+                        sqlx::query_file!("src/queries/mark_outbox_event_dispatched.sql", event.id)
+                            .execute(&context.database_client)
+                            .await?;
+                        dispatched += 1;
+                        continue;
+                    }
+                    Err(e) => (e.to_string(), false),
+                },
+                // Retrying won't make the stored attributes any better.
+                Err(e) => (format!("Invalid attributes: {}", e), true),
+            };
+
+        let parked = sqlx::query_file!(
+            "src/queries/mark_outbox_event_failed.sql",
+            event.id,
+            error,
+            max_attempts,
+            permanent
+        )
+        .fetch_one(&context.database_client)
+        .await?
+        .parked;
+        if parked {
+            error!("Parked outbox event {}: {}", event.id, error);
+            continue;
+        }
+
+        warn!("Failed to dispatch outbox event {}: {}", event.id, error);
+        // Keep the order, later events wait until this one went through.
+        let unpublished = pending.map(|x| x.id).collect::<Vec<_>>();
+        sqlx::query_file!("src/queries/release_outbox_events.sql", &unpublished)
+            .execute(&context.database_client)
+            .await?;
+        break;
+    }
+
+    if dispatched > 0 {
+        info!(
+            "Dispatched {} outbox event(s) via {}",
+            dispatched,
+            context.notifier.name()
+        );
+    }
+    Ok(dispatched)
+}
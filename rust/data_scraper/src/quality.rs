@@ -0,0 +1,559 @@
+//! Sanity checks on the numbers of a month, run after it is stored. The rules are
+//! configured per series, violations are stored next to the rows and counted in the
+//! run ledger, they don't stop the import.
+
+use std::fmt::{Display, Formatter};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::events::YearMonth;
+use crate::helpers::{DestatisValue, LAENDER};
+use crate::metrics::METRICS;
+use crate::veraenderung::{self, change_percent, PreviousYearRows};
+
+/// Names of Germany as a whole in the `land` column.
+const LAND_TOTAL: [&str; 3] = ["Gesamt", "Insgesamt", "Deutschland"];
+/// Names of all guests in the `wohnsitz` and `herkunftsregion` columns.
+const HERKUNFT_TOTAL: [&str; 2] = ["Gesamt", "Insgesamt"];
+const HERKUNFT_INLAND: [&str; 2] = ["Inland", "Deutschland"];
+const HERKUNFT_AUSLAND: [&str; 1] = ["Ausland"];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "rule", rename_all = "snake_case", deny_unknown_fields)]
+pub enum QualityRule {
+    /// The 16 Länder add up to Germany as a whole, for each kind of residence.
+    LaenderSum {
+        /// Relative to the total.
+        #[serde(default = "default_sum_tolerance")]
+        tolerance: f64,
+    },
+    /// Guests from Germany and from abroad add up to all guests.
+    InlandAuslandSum {
+        /// Relative to the total.
+        #[serde(default = "default_sum_tolerance")]
+        tolerance: f64,
+    },
+    /// The average length of stay is overnight stays per arrival.
+    Aufenthaltsdauer {
+        /// In days, it is published with one decimal.
+        #[serde(default = "default_aufenthaltsdauer_tolerance")]
+        tolerance: f64,
+    },
+    /// The change to the same month of the previous year matches the stored counts
+    /// of that month. A mismatch usually means those counts were revised since.
+    Veraenderung {
+        /// In percentage points, it is published with one decimal.
+        #[serde(default = "default_veraenderung_tolerance")]
+        tolerance: f64,
+    },
+    /// Counts and lengths of stay are never negative.
+    NonNegative,
+}
+
+fn default_sum_tolerance() -> f64 {
+    0.001
+}
+
+fn default_aufenthaltsdauer_tolerance() -> f64 {
+    0.051
+}
+
+fn default_veraenderung_tolerance() -> f64 {
+    veraenderung::TOLERANCE
+}
+
+pub fn default_quality_rules() -> Vec<QualityRule> {
+    vec![
+        QualityRule::LaenderSum {
+            tolerance: default_sum_tolerance(),
+        },
+        QualityRule::InlandAuslandSum {
+            tolerance: default_sum_tolerance(),
+        },
+        QualityRule::Aufenthaltsdauer {
+            tolerance: default_aufenthaltsdauer_tolerance(),
+        },
+        QualityRule::Veraenderung {
+            tolerance: default_veraenderung_tolerance(),
+        },
+        QualityRule::NonNegative,
+    ]
+}
+
+impl QualityRule {
+    pub fn code(&self) -> &'static str {
+        match self {
+            QualityRule::LaenderSum { .. } => "laender_sum",
+            QualityRule::InlandAuslandSum { .. } => "inland_ausland_sum",
+            QualityRule::Aufenthaltsdauer { .. } => "aufenthaltsdauer",
+            QualityRule::Veraenderung { .. } => "veraenderung",
+            QualityRule::NonNegative => "non_negative",
+        }
+    }
+}
+
+/// The numbers of a row, whichever table it comes from.
+pub struct RowFigures<'a> {
+    /// `None` for tables that aren't split by Land.
+    pub land: Option<&'a str>,
+    /// The residence or country of origin of the guests.
+    pub herkunft: &'a str,
+    pub ankuenfte: DestatisValue<i64>,
+    pub ankuenfte_veraenderung: DestatisValue<f64>,
+    pub uebernachtungen: DestatisValue<i64>,
+    pub uebernachtungen_veraenderung: DestatisValue<f64>,
+    pub aufenthaltsdauer: DestatisValue<f64>,
+}
+
+/// A month of one table, as the rules see it.
+pub struct MonthFigures<'a> {
+    pub table: &'static str,
+    pub period: YearMonth,
+    pub rows: Vec<RowFigures<'a>>,
+    pub previous_year: PreviousYearRows,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QualityViolation {
+    pub rule: &'static str,
+    pub table: &'static str,
+    pub period: YearMonth,
+    /// Land and herkunft of the row, or just the herkunft.
+    pub subject: String,
+    pub field: &'static str,
+    pub expected: Option<f64>,
+    pub actual: f64,
+}
+
+impl Display for QualityViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}-{:02} {}: {} is {}",
+            self.table, self.period.year, self.period.month, self.subject, self.field, self.actual
+        )?;
+        if let Some(expected) = self.expected {
+            write!(f, ", expected {}", expected)?;
+        }
+        write!(f, " ({})", self.rule)
+    }
+}
+
+impl RowFigures<'_> {
+    fn subject(&self) -> String {
+        match self.land {
+            Some(land) => format!("{} / {}", land, self.herkunft),
+            None => self.herkunft.to_string(),
+        }
+    }
+
+    fn counts(&self) -> [(&'static str, Option<i64>); 2] {
+        [
+            ("ankuenfte_anzahl", self.ankuenfte.value),
+            ("uebernachtungen_anzahl", self.uebernachtungen.value),
+        ]
+    }
+}
+
+/// Runs the rules on the month and returns everything that doesn't add up.
+pub fn check(rules: &[QualityRule], month: &MonthFigures) -> Vec<QualityViolation> {
+    let mut violations = Vec::new();
+    for rule in rules {
+        let mut violation = |row_subject: String, field, expected, actual| {
+            violations.push(QualityViolation {
+                rule: rule.code(),
+                table: month.table,
+                period: month.period,
+                subject: row_subject,
+                field,
+                expected,
+                actual,
+            })
+        };
+        match rule {
+            QualityRule::LaenderSum { tolerance } => {
+                for total in month.rows.iter().filter(|x| is_land_total(x.land)) {
+                    let laender = month
+                        .rows
+                        .iter()
+                        .filter(|x| x.herkunft == total.herkunft)
+                        .filter(|x| x.land.is_some_and(|land| LAENDER.contains(&land)))
+                        .collect::<Vec<_>>();
+                    if laender.len() != LAENDER.len() {
+                        continue;
+                    }
+                    for (i, (field, expected)) in total.counts().into_iter().enumerate() {
+                        let sum = laender.iter().map(|x| x.counts()[i].1).sum::<Option<i64>>();
+                        if let (Some(expected), Some(sum)) = (expected, sum) {
+                            if !within(sum as f64, expected as f64, *tolerance) {
+                                violation(
+                                    total.subject(),
+                                    field,
+                                    Some(expected as f64),
+                                    sum as f64,
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+            QualityRule::InlandAuslandSum { tolerance } => {
+                for total in month
+                    .rows
+                    .iter()
+                    .filter(|x| HERKUNFT_TOTAL.contains(&x.herkunft))
+                {
+                    let part = |names: &[&str]| {
+                        month
+                            .rows
+                            .iter()
+                            .find(|x| x.land == total.land && names.contains(&x.herkunft))
+                    };
+                    let (Some(inland), Some(ausland)) =
+                        (part(&HERKUNFT_INLAND), part(&HERKUNFT_AUSLAND))
+                    else {
+                        continue;
+                    };
+                    for (i, (field, expected)) in total.counts().into_iter().enumerate() {
+                        if let (Some(expected), Some(inland), Some(ausland)) =
+                            (expected, inland.counts()[i].1, ausland.counts()[i].1)
+                        {
+                            let sum = (inland + ausland) as f64;
+                            if !within(sum, expected as f64, *tolerance) {
+                                violation(total.subject(), field, Some(expected as f64), sum);
+                            }
+                        }
+                    }
+                }
+            }
+            QualityRule::Aufenthaltsdauer { tolerance } => {
+                for row in &month.rows {
+                    let (Some(ankuenfte), Some(uebernachtungen), Some(dauer)) = (
+                        row.ankuenfte.value,
+                        row.uebernachtungen.value,
+                        row.aufenthaltsdauer.value,
+                    ) else {
+                        continue;
+                    };
+                    if ankuenfte <= 0 {
+                        continue;
+                    }
+                    let expected = uebernachtungen as f64 / ankuenfte as f64;
+                    if (dauer - expected).abs() > *tolerance {
+                        violation(
+                            row.subject(),
+                            "durchsch_aufenthaltsdauer_tage",
+                            Some(expected),
+                            dauer,
+                        );
+                    }
+                }
+            }
+            QualityRule::Veraenderung { tolerance } => {
+                for row in &month.rows {
+                    let Some(previous) = month
+                        .previous_year
+                        .get(&(row.land.map(str::to_string), row.herkunft.to_string()))
+                    else {
+                        continue;
+                    };
+                    let pairs = [
+                        (
+                            "ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent",
+                            row.ankuenfte.value,
+                            previous.ankuenfte,
+                            row.ankuenfte_veraenderung.value,
+                        ),
+                        (
+                            "uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent",
+                            row.uebernachtungen.value,
+                            previous.uebernachtungen,
+                            row.uebernachtungen_veraenderung.value,
+                        ),
+                    ];
+                    for (field, current, previous, published) in pairs {
+                        let (Some(expected), Some(published)) =
+                            (change_percent(current, previous), published)
+                        else {
+                            continue;
+                        };
+                        if (published - expected).abs() > *tolerance {
+                            violation(row.subject(), field, Some(expected), published);
+                        }
+                    }
+                }
+            }
+            QualityRule::NonNegative => {
+                for row in &month.rows {
+                    for (field, value) in row.counts() {
+                        if let Some(value) = value.filter(|x| *x < 0) {
+                            violation(row.subject(), field, None, value as f64);
+                        }
+                    }
+                    if let Some(value) = row.aufenthaltsdauer.value.filter(|x| *x < 0.0) {
+                        violation(row.subject(), "durchsch_aufenthaltsdauer_tage", None, value);
+                    }
+                }
+            }
+        }
+    }
+    violations
+}
+
+fn is_land_total(land: Option<&str>) -> bool {
+    land.is_some_and(|x| LAND_TOTAL.contains(&x))
+}
+
+fn within(actual: f64, expected: f64, tolerance: f64) -> bool {
+    (actual - expected).abs() <= expected.abs() * tolerance
+}
+
+/// Replaces the violations of the month of the series in the table with the given ones.
+pub async fn store_violations(
+    tx: &mut PgConnection,
+    run_id: Uuid,
+    series_id: &str,
+    table: &str,
+    period: YearMonth,
+    violations: &[QualityViolation],
+) -> Result<(), anyhow::Error> {
+    sqlx::query_file!(
+        "src/queries/delete_quality_violations_for_month.sql",
+        table,
+        period.year,
+        period.month as i32,
+        series_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    for x in violations {
+        sqlx::query_file!(
+            "src/queries/insert_quality_violation.sql",
+            run_id,
+            x.table,
+            x.period.year,
+            x.period.month as i32,
+            x.rule,
+            x.subject,
+            x.field,
+            x.expected,
+            x.actual,
+            series_id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Logs the violations and counts them in the metrics.
+pub fn log_violations(violations: &[QualityViolation]) {
+    for x in violations {
+        warn!("Data quality: {}", x);
+        METRICS
+            .quality_violations
+            .with_label_values(&[x.rule])
+            .inc();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::veraenderung::PreviousYear;
+
+    const PERIOD: YearMonth = YearMonth {
+        year: 2024,
+        month: 2,
+    };
+
+    fn row<'a>(
+        land: Option<&'a str>,
+        herkunft: &'a str,
+        ankuenfte: i64,
+        uebernachtungen: i64,
+    ) -> RowFigures<'a> {
+        RowFigures {
+            land,
+            herkunft,
+            ankuenfte: DestatisValue::new(ankuenfte),
+            ankuenfte_veraenderung: DestatisValue::default(),
+            uebernachtungen: DestatisValue::new(uebernachtungen),
+            uebernachtungen_veraenderung: DestatisValue::default(),
+            aufenthaltsdauer: DestatisValue::new(
+                (uebernachtungen as f64 / ankuenfte as f64 * 10.0).round() / 10.0,
+            ),
+        }
+    }
+
+    fn month(rows: Vec<RowFigures>) -> MonthFigures {
+        MonthFigures {
+            table: "uebernachtungen_pro_land",
+            period: PERIOD,
+            rows,
+            previous_year: PreviousYearRows::new(),
+        }
+    }
+
+    /// Germany and every Land with 10 arrivals and 30 overnight stays.
+    fn laender(total_ankuenfte: i64) -> Vec<RowFigures<'static>> {
+        let mut rows = vec![row(Some("Gesamt"), "Gesamt", total_ankuenfte, 480)];
+        rows.extend(LAENDER.iter().map(|x| row(Some(x), "Gesamt", 10, 30)));
+        rows
+    }
+
+    fn fields(violations: &[QualityViolation]) -> Vec<(&str, String, &str, Option<f64>, f64)> {
+        violations
+            .iter()
+            .map(|x| (x.rule, x.subject.clone(), x.field, x.expected, x.actual))
+            .collect()
+    }
+
+    #[test]
+    fn a_consistent_month_has_no_violations() {
+        let mut rows = laender(160);
+        rows.push(row(Some("Bayern"), "Inland", 6, 20));
+        rows.push(row(Some("Bayern"), "Ausland", 4, 10));
+        assert!(check(&default_quality_rules(), &month(rows)).is_empty());
+    }
+
+    #[test]
+    fn checks_the_sum_of_the_laender() {
+        let rules = [QualityRule::LaenderSum { tolerance: 0.001 }];
+        assert_eq!(
+            fields(&check(&rules, &month(laender(170)))),
+            [(
+                "laender_sum",
+                "Gesamt / Gesamt".to_string(),
+                "ankuenfte_anzahl",
+                Some(170.0),
+                160.0
+            )]
+        );
+
+        // Within the tolerance, or with a Land missing, there is nothing to compare.
+        let rules = [QualityRule::LaenderSum { tolerance: 0.1 }];
+        assert!(check(&rules, &month(laender(170))).is_empty());
+        let mut rows = laender(170);
+        rows.pop();
+        assert!(check(&rules, &month(rows)).is_empty());
+    }
+
+    #[test]
+    fn checks_inland_and_ausland() {
+        let rules = [QualityRule::InlandAuslandSum { tolerance: 0.001 }];
+        let rows = vec![
+            row(None, "Insgesamt", 100, 300),
+            row(None, "Inland", 60, 200),
+            row(None, "Ausland", 30, 100),
+            // Guests of another Land don't count towards the national total.
+            row(Some("Bayern"), "Ausland", 40, 100),
+        ];
+        assert_eq!(
+            fields(&check(&rules, &month(rows))),
+            [(
+                "inland_ausland_sum",
+                "Insgesamt".to_string(),
+                "ankuenfte_anzahl",
+                Some(100.0),
+                90.0
+            )]
+        );
+    }
+
+    #[test]
+    fn checks_the_length_of_stay() {
+        let rules = [QualityRule::Aufenthaltsdauer { tolerance: 0.051 }];
+        let mut rounded = row(None, "Inland", 300, 1000);
+        rounded.aufenthaltsdauer = DestatisValue::new(3.3);
+        let mut wrong = row(None, "Ausland", 100, 300);
+        wrong.aufenthaltsdauer = DestatisValue::new(3.2);
+        let mut no_arrivals = row(None, "Insgesamt", 0, 0);
+        no_arrivals.aufenthaltsdauer = DestatisValue::new(1.0);
+
+        let violations = check(&rules, &month(vec![rounded, wrong, no_arrivals]));
+        assert_eq!(
+            fields(&violations),
+            [(
+                "aufenthaltsdauer",
+                "Ausland".to_string(),
+                "durchsch_aufenthaltsdauer_tage",
+                Some(3.0),
+                3.2
+            )]
+        );
+    }
+
+    #[test]
+    fn checks_for_negative_numbers() {
+        let rules = [QualityRule::NonNegative];
+        let mut negative = row(None, "Ausland", -5, 10);
+        negative.aufenthaltsdauer = DestatisValue::new(-2.0);
+        let violations = check(&rules, &month(vec![row(None, "Inland", 0, 0), negative]));
+        assert_eq!(
+            fields(&violations),
+            [
+                (
+                    "non_negative",
+                    "Ausland".to_string(),
+                    "ankuenfte_anzahl",
+                    None,
+                    -5.0
+                ),
+                (
+                    "non_negative",
+                    "Ausland".to_string(),
+                    "durchsch_aufenthaltsdauer_tage",
+                    None,
+                    -2.0
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_the_change_to_the_previous_year() {
+        let rules = [QualityRule::Veraenderung { tolerance: 0.1 }];
+        let mut matching = row(None, "Inland", 100, 300);
+        matching.ankuenfte_veraenderung = DestatisValue::new(25.0);
+        matching.uebernachtungen_veraenderung = DestatisValue::new(50.04);
+        let mut revised = row(None, "Ausland", 100, 300);
+        revised.ankuenfte_veraenderung = DestatisValue::new(20.0);
+        let mut without_previous = row(None, "Insgesamt", 100, 300);
+        without_previous.ankuenfte_veraenderung = DestatisValue::new(-50.0);
+        let mut previous_zero = row(None, "Niederlande", 100, 300);
+        previous_zero.ankuenfte_veraenderung = DestatisValue::new(-50.0);
+
+        let mut month = month(vec![matching, revised, without_previous, previous_zero]);
+        let previous = PreviousYear {
+            ankuenfte: Some(80),
+            uebernachtungen: Some(200),
+        };
+        for herkunft in ["Inland", "Ausland"] {
+            month
+                .previous_year
+                .insert((None, herkunft.to_string()), previous);
+        }
+        month.previous_year.insert(
+            (None, "Niederlande".to_string()),
+            PreviousYear {
+                ankuenfte: Some(0),
+                uebernachtungen: Some(0),
+            },
+        );
+
+        assert_eq!(
+            fields(&check(&rules, &month)),
+            [(
+                "veraenderung",
+                "Ausland".to_string(),
+                "ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent",
+                Some(25.0),
+                20.0
+            )]
+        );
+    }
+}
@@ -0,0 +1,317 @@
+//! Workbooks that can't be parsed, and rows that were skipped, are set aside here
+//! instead of failing the whole run.
+//!
+//! A quarantined workbook is skipped until its entries are released, a new file for
+//! the same publication is imported as usual. `retry` imports the workbook again,
+//! e.g. after a parser fix, and releases its entries if that works.
+
+use std::fs::File;
+
+use chrono::{DateTime, Utc};
+use clap::Subcommand;
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::archive::{ArtifactKind, ArtifactOrigin};
+use crate::context::Context;
+use crate::events::YearMonth;
+use crate::helpers::RowError;
+use crate::mycore::MyCoReClient;
+use crate::tasks::{configured_task, GetSleepoverDataTask, SeriesConfig};
+
+#[derive(Subcommand)]
+pub enum QuarantineCommand {
+    /// List the quarantined workbooks and rows.
+    List {
+        /// Include entries that were released already.
+        #[arg(long)]
+        all: bool,
+    },
+    /// Print an entry with its error details and raw row.
+    Inspect { id: i64 },
+    /// Import the workbook of an entry again and release its entries if that works.
+    /// It is taken from the archive, or downloaded again if it isn't archived.
+    Retry { id: i64 },
+}
+
+/// The workbook an import works on, everything quarantined from it points here.
+pub struct WorkbookSource<'a> {
+    pub run_id: Uuid,
+    pub series_id: &'a str,
+    pub mods_id: Option<&'a str>,
+    pub url: &'a str,
+    pub md5: &'a str,
+    pub period: YearMonth,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuarantineEntry {
+    pub id: i64,
+    /// `workbook` or `row`.
+    pub kind: String,
+    pub run_id: Uuid,
+    pub series_id: String,
+    pub mods_id: Option<String>,
+    pub source_url: String,
+    pub source_md5: String,
+    pub period_year: Option<i32>,
+    pub period_month: Option<i32>,
+    pub sheet: Option<String>,
+    pub row_index: Option<i32>,
+    pub raw_row: Option<Value>,
+    pub error: String,
+    pub details: Option<Value>,
+    pub attempts: i32,
+    pub last_retry_error: Option<String>,
+    pub quarantined_at: DateTime<Utc>,
+    pub retried_at: Option<DateTime<Utc>>,
+    pub released_at: Option<DateTime<Utc>>,
+}
+
+/// Quarantines the whole workbook, it is skipped by later runs.
+pub async fn quarantine_workbook(
+    database: &PgPool,
+    source: &WorkbookSource<'_>,
+    sheet: Option<&str>,
+    error: &str,
+    details: Option<Value>,
+) -> Result<i64, anyhow::Error> {
+    let id = sqlx::query_file!(
+        "src/queries/insert_quarantine.sql",
+        "workbook",
+        source.run_id,
+        source.series_id,
+        source.mods_id,
+        source.url,
+        source.md5,
+        source.period.year,
+        source.period.month as i32,
+        sheet,
+        None::<i32>,
+        None::<Value>,
+        error,
+        details
+    )
+    .fetch_one(database)
+    .await?
+    .id;
+    warn!("Quarantined {} as entry {}: {}", source.url, id, error);
+    Ok(id)
+}
+
+/// Quarantines rows that were skipped while the rest of the workbook is imported.
+pub async fn quarantine_rows(
+    tx: &mut PgConnection,
+    source: &WorkbookSource<'_>,
+    rows: &[RowError],
+) -> Result<(), anyhow::Error> {
+    for row in rows {
+        sqlx::query_file!(
+            "src/queries/insert_quarantine.sql",
+            "row",
+            source.run_id,
+            source.series_id,
+            source.mods_id,
+            source.url,
+            source.md5,
+            source.period.year,
+            source.period.month as i32,
+            Some(row.sheet.as_str()),
+            Some(row.row as i32),
+            Some(&row.raw_row),
+            row.to_string(),
+            Some(serde_json::to_value(row)?)
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+    }
+    if !rows.is_empty() {
+        warn!("Quarantined {} row(s) of {}", rows.len(), source.url);
+    }
+    Ok(())
+}
+
+pub async fn is_quarantined(database: &PgPool, md5: &str) -> Result<bool, anyhow::Error> {
+    Ok(
+        sqlx::query_file!("src/queries/select_source_quarantined.sql", md5)
+            .fetch_one(database)
+            .await?
+            .quarantined,
+    )
+}
+
+/// Releases the entries of the workbook, except the ones the given run just created.
+pub async fn release_source(
+    tx: &mut PgConnection,
+    md5: &str,
+    run_id: Uuid,
+) -> Result<u64, anyhow::Error> {
+    Ok(
+        sqlx::query_file!("src/queries/release_quarantined_source.sql", md5, run_id)
+            .execute(tx)
+            .await?
+            .rows_affected(),
+    )
+}
+
+async fn entries(
+    database: &PgPool,
+    include_released: bool,
+    id: Option<i64>,
+) -> Result<Vec<QuarantineEntry>, anyhow::Error> {
+    Ok(sqlx::query_file_as!(
+        QuarantineEntry,
+        "src/queries/select_quarantine_entries.sql",
+        include_released,
+        id
+    )
+    .fetch_all(database)
+    .await?)
+}
+
+async fn entry(database: &PgPool, id: i64) -> Result<QuarantineEntry, anyhow::Error> {
+    entries(database, true, Some(id))
+        .await?
+        .pop()
+        .ok_or(anyhow::anyhow!("Quarantine entry {} not found", id))
+}
+
+pub async fn run(context: Context, command: QuarantineCommand) -> Result<(), anyhow::Error> {
+    let database = &context.database_client;
+    match command {
+        QuarantineCommand::List { all } => {
+            let entries = entries(database, all, None).await?;
+            for x in &entries {
+                let period = match (x.period_year, x.period_month) {
+                    (Some(year), Some(month)) => format!("{}-{:02}", year, month),
+                    _ => "-".to_string(),
+                };
+                let location = match (&x.sheet, x.row_index) {
+                    (Some(sheet), Some(row)) => format!("{}:{}", sheet, row),
+                    (Some(sheet), None) => sheet.clone(),
+                    _ => "-".to_string(),
+                };
+                println!(
+                    "{}\t{}\t{}\t{}\t{}\t{}{}",
+                    x.id,
+                    x.kind,
+                    period,
+                    location,
+                    x.quarantined_at.format("%Y-%m-%d %H:%M"),
+                    x.error,
+                    if x.released_at.is_some() {
+                        " (released)"
+                    } else {
+                        ""
+                    }
+                );
+            }
+            info!("{} entries", entries.len());
+            Ok(())
+        }
+        QuarantineCommand::Inspect { id } => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&entry(database, id).await?)?
+            );
+            Ok(())
+        }
+        QuarantineCommand::Retry { id } => retry(&context, entry(database, id).await?).await,
+    }
+}
+
+async fn retry(context: &Context, entry: QuarantineEntry) -> Result<(), anyhow::Error> {
+    if entry.released_at.is_some() {
+        return Err(anyhow::anyhow!("Entry {} was released already", entry.id));
+    }
+    let (Some(year), Some(month)) = (entry.period_year, entry.period_month) else {
+        return Err(anyhow::anyhow!(
+            "Entry {} has no period, it can't be retried",
+            entry.id
+        ));
+    };
+
+    let task = configured_task::<GetSleepoverDataTask>(&context.settings)?;
+    let series = task.series(&entry.series_id).ok_or(anyhow::anyhow!(
+        "Series {} is not configured",
+        entry.series_id
+    ))?;
+    let period = YearMonth {
+        year,
+        month: month as u32,
+    };
+    let file = match context
+        .archive
+        .find(ArtifactKind::Workbook, &entry.source_md5)
+        .await?
+    {
+        Some(artifact) => context.archive.get_to_tempfile(&artifact).await?,
+        None => download_again(context, series, &entry, period).await?,
+    };
+
+    let source = WorkbookSource {
+        run_id: Uuid::new_v4(),
+        series_id: &entry.series_id,
+        mods_id: entry.mods_id.as_deref(),
+        url: &entry.source_url,
+        md5: &entry.source_md5,
+        period,
+    };
+    info!(
+        "Retrying {} {} from {} as run {}",
+        year, month, entry.source_url, source.run_id
+    );
+    if let Err(e) = task
+        .import_workbook(context, series, &file, &source, entry.mods_id.as_deref())
+        .await
+    {
+        sqlx::query_file!(
+            "src/queries/mark_quarantine_retry_failed.sql",
+            entry.source_md5,
+            e.to_string()
+        )
+        .execute(&context.database_client)
+        .await?;
+        return Err(e);
+    }
+
+    info!("Imported {}, its entries are released", entry.source_url);
+    Ok(())
+}
+
+/// Downloads the quarantined workbook from its publication again, for when it isn't
+/// archived.
+async fn download_again(
+    context: &Context,
+    series: &SeriesConfig,
+    entry: &QuarantineEntry,
+    period: YearMonth,
+) -> Result<File, anyhow::Error> {
+    let mods_id = entry.mods_id.as_deref().ok_or(anyhow::anyhow!(
+        "{} is not archived and has no publication to download it from",
+        entry.source_url
+    ))?;
+    info!(
+        "{} is not archived, downloading it from {} again",
+        entry.source_url, mods_id
+    );
+    let mycore = MyCoReClient::new(context);
+    let workbook = mycore.find_file(mods_id, &series.extensions).await?;
+    if !workbook.md5.eq_ignore_ascii_case(&entry.source_md5) {
+        return Err(anyhow::anyhow!(
+            "{} is not archived and {} now has {} instead, the next scheduled run imports that one",
+            entry.source_url,
+            mods_id,
+            workbook.href
+        ));
+    }
+    let origin = ArtifactOrigin {
+        series_id: Some(series.id.clone()),
+        mods_id: Some(mods_id.to_string()),
+        period: Some(period),
+    };
+    Ok(mycore.download(&workbook, origin).await?)
+}
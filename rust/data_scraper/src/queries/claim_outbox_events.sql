@@ -0,0 +1,12 @@
+WITH pending AS (SELECT id
+                 FROM data_scraper.outbox
+                 WHERE dispatched_at IS NULL
+                   AND parked_at IS NULL
+                   AND (claimed_until IS NULL OR claimed_until < now())
+                 ORDER BY id
+                 LIMIT $1 FOR UPDATE SKIP LOCKED)
+UPDATE data_scraper.outbox
+SET claimed_until = now() + make_interval(secs => $2)
+FROM pending
+WHERE outbox.id = pending.id
+RETURNING outbox.id, outbox.topic, outbox.payload, outbox.attributes;
@@ -0,0 +1,6 @@
+DELETE
+FROM data_scraper.quality_violations
+WHERE table_name = $1
+  AND period_year = $2
+  AND period_month = $3
+  AND series_id = $4;
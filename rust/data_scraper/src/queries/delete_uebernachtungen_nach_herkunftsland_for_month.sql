@@ -0,0 +1,5 @@
+DELETE
+FROM original_data.uebernachtungen_nach_herkunftsland
+WHERE jahr = $1
+  AND monat = $2
+  AND series_id = $3;
@@ -2,5 +2,10 @@ INSERT INTO original_data.uebernachtungen_pro_land (land, wohnsitz, jahr, monat,
                                                     ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent,
                                                     uebernachtungen_anzahl,
                                                     uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent,
-                                                    durchsch_aufenthaltsdauer_tage)
-VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9);
+                                                    durchsch_aufenthaltsdauer_tage, publication_id,
+                                                    ankuenfte_anzahl_flag,
+                                                    ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent_flag,
+                                                    uebernachtungen_anzahl_flag,
+                                                    uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent_flag,
+                                                    durchsch_aufenthaltsdauer_tage_flag, series_id)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16);
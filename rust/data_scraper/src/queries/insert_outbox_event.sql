@@ -0,0 +1,2 @@
+INSERT INTO data_scraper.outbox (topic, payload, attributes)
+VALUES ($1, $2, $3);
@@ -0,0 +1,3 @@
+INSERT INTO data_scraper.quality_violations (run_id, table_name, period_year, period_month, rule, subject, field,
+                                             expected, actual, series_id)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10);
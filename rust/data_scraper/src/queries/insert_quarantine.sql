@@ -0,0 +1,4 @@
+INSERT INTO data_scraper.quarantine (kind, run_id, series_id, mods_id, source_url, source_md5, period_year,
+                                     period_month, sheet, row_index, raw_row, error, details)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+RETURNING id;
@@ -0,0 +1,6 @@
+UPDATE data_scraper.outbox
+SET dispatched_at = now(),
+    claimed_until = NULL,
+    attempts      = attempts + 1,
+    last_error    = NULL
+WHERE id = $1;
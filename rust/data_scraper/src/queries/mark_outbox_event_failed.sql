@@ -0,0 +1,7 @@
+UPDATE data_scraper.outbox
+SET claimed_until = NULL,
+    attempts      = attempts + 1,
+    last_error    = $2,
+    parked_at     = CASE WHEN $4 OR attempts + 1 >= $3 THEN now() END
+WHERE id = $1
+RETURNING parked_at IS NOT NULL AS "parked!";
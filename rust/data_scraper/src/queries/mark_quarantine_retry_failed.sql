@@ -0,0 +1,6 @@
+UPDATE data_scraper.quarantine
+SET attempts         = attempts + 1,
+    last_retry_error = $2,
+    retried_at       = now()
+WHERE source_md5 = $1
+  AND released_at IS NULL;
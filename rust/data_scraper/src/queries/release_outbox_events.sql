@@ -0,0 +1,3 @@
+UPDATE data_scraper.outbox
+SET claimed_until = NULL
+WHERE id = ANY ($1);
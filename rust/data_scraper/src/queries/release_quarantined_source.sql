@@ -0,0 +1,5 @@
+UPDATE data_scraper.quarantine
+SET released_at = now()
+WHERE source_md5 = $1
+  AND run_id <> $2
+  AND released_at IS NULL;
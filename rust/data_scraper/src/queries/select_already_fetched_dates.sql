@@ -1,2 +1,3 @@
 SELECT distinct monat, jahr
-from original_data.uebernachtungen_pro_land;
+from original_data.uebernachtungen_pro_land
+WHERE series_id = $1;
@@ -0,0 +1,3 @@
+SELECT distinct monat, jahr
+from original_data.uebernachtungen_nach_herkunftsland
+WHERE series_id = $1;
@@ -0,0 +1,23 @@
+SELECT id,
+       kind,
+       run_id,
+       series_id,
+       mods_id,
+       source_url,
+       source_md5,
+       period_year,
+       period_month,
+       sheet,
+       row_index,
+       raw_row,
+       error,
+       details,
+       attempts,
+       last_retry_error,
+       quarantined_at,
+       retried_at,
+       released_at
+FROM data_scraper.quarantine
+WHERE ($1 OR released_at IS NULL)
+  AND ($2::BIGINT IS NULL OR id = $2)
+ORDER BY id;
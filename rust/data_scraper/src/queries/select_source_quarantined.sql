@@ -0,0 +1,5 @@
+SELECT EXISTS(SELECT 1
+              FROM data_scraper.quarantine
+              WHERE source_md5 = $1
+                AND kind = 'workbook'
+                AND released_at IS NULL) AS "quarantined!";
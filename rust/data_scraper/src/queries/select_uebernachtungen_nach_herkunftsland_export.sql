@@ -0,0 +1,12 @@
+SELECT series_id,
+       herkunftsregion,
+       jahr,
+       monat,
+       ankuenfte_anzahl,
+       ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent,
+       uebernachtungen_anzahl,
+       uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent,
+       durchsch_aufenthaltsdauer_tage,
+       publication_id
+FROM original_data.uebernachtungen_nach_herkunftsland
+ORDER BY series_id, jahr, monat, herkunftsregion;
@@ -0,0 +1,13 @@
+SELECT series_id,
+       land,
+       wohnsitz,
+       jahr,
+       monat,
+       ankuenfte_anzahl,
+       ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent,
+       uebernachtungen_anzahl,
+       uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent,
+       durchsch_aufenthaltsdauer_tage,
+       publication_id
+FROM original_data.uebernachtungen_pro_land
+ORDER BY series_id, jahr, monat, land, wohnsitz;
@@ -0,0 +1,10 @@
+SELECT land,
+       wohnsitz,
+       ankuenfte_anzahl,
+       ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent,
+       uebernachtungen_anzahl,
+       uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent
+FROM original_data.uebernachtungen_pro_land
+WHERE jahr = $1
+  AND monat = $2
+  AND series_id = $3;
@@ -0,0 +1,9 @@
+UPDATE original_data.uebernachtungen_nach_herkunftsland
+SET ankuenfte_veraenderung_berechnet_prozent       = $4,
+    ankuenfte_veraenderung_abgleich                = $5,
+    uebernachtungen_veraenderung_berechnet_prozent = $6,
+    uebernachtungen_veraenderung_abgleich          = $7
+WHERE jahr = $1
+  AND monat = $2
+  AND herkunftsregion = $3
+  AND series_id = $8;
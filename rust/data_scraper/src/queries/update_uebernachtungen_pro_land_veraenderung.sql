@@ -0,0 +1,10 @@
+UPDATE original_data.uebernachtungen_pro_land
+SET ankuenfte_veraenderung_berechnet_prozent       = $5,
+    ankuenfte_veraenderung_abgleich                = $6,
+    uebernachtungen_veraenderung_berechnet_prozent = $7,
+    uebernachtungen_veraenderung_abgleich          = $8
+WHERE jahr = $1
+  AND monat = $2
+  AND land = $3
+  AND wohnsitz = $4
+  AND series_id = $9;
@@ -0,0 +1,12 @@
+INSERT INTO data_scraper.publications (mods_id, series_id, title, reference_period_start, reference_period_end,
+                                       published_on, edition, notes, license, fetched_at)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, now())
+ON CONFLICT (mods_id) DO UPDATE SET series_id              = excluded.series_id,
+                                    title                  = excluded.title,
+                                    reference_period_start = excluded.reference_period_start,
+                                    reference_period_end   = excluded.reference_period_end,
+                                    published_on           = excluded.published_on,
+                                    edition                = excluded.edition,
+                                    notes                  = excluded.notes,
+                                    license                = excluded.license,
+                                    fetched_at             = excluded.fetched_at;
@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+
+use log::{info, warn};
+use uuid::Uuid;
+
+use crate::archive::ArtifactKind;
+use crate::context::Context;
+use crate::quarantine::WorkbookSource;
+use crate::tasks::{configured_task, GetSleepoverDataTask, MONATSBERICHT_TOURISMUS_SERIES};
+
+/// Imports the archived workbooks again, e.g. after a parser fix. When a month was
+/// archived more than once, the most recently fetched workbook wins.
+pub async fn run(context: Context, mods_id: Option<&str>) -> Result<(), anyhow::Error> {
+    let run_id = Uuid::new_v4();
+    let artifacts = context.archive.list(ArtifactKind::Workbook).await?;
+    info!(
+        "Found {} archived workbook(s), reprocessing as run {}",
+        artifacts.len(),
+        run_id
+    );
+
+    let task = configured_task::<GetSleepoverDataTask>(&context.settings)?;
+
+    let mut by_period = BTreeMap::new();
+    for artifact in artifacts {
+        if mods_id.is_some_and(|x| artifact.origin.mods_id.as_deref() != Some(x)) {
+            continue;
+        }
+        // Workbooks archived before there were several series all belong to the first one.
+        let series_id = artifact
+            .origin
+            .series_id
+            .clone()
+            .unwrap_or_else(|| MONATSBERICHT_TOURISMUS_SERIES.to_string());
+        match artifact.origin.period {
+            Some(period) => {
+                by_period.insert((series_id, period), artifact);
+            }
+            None => warn!("Skipping {}, it has no period", artifact.url),
+        }
+    }
+    if by_period.is_empty() {
+        return Err(anyhow::anyhow!("No archived workbooks to reprocess"));
+    }
+
+    for ((series_id, period), artifact) in by_period {
+        context.ensure_not_cancelled()?;
+        let Some(series) = task.series(&series_id) else {
+            warn!(
+                "Skipping {}, series {} is not configured",
+                artifact.url, series_id
+            );
+            continue;
+        };
+
+        let file = context.archive.get_to_tempfile(&artifact).await?;
+
+        info!(
+            "Reprocessing {} {} from {}",
+            period.year, period.month, artifact.url
+        );
+        let source = WorkbookSource {
+            run_id,
+            series_id: &series.id,
+            mods_id: artifact.origin.mods_id.as_deref(),
+            url: &artifact.url,
+            md5: &artifact.md5,
+            period,
+        };
+        task.import_workbook(&context, series, &file, &source, source.mods_id)
+            .await?;
+    }
+
+    info!("Reprocessing finished");
+    Ok(())
+}
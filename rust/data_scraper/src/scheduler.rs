@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use tokio::select;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio::time::MissedTickBehavior;
+use uuid::Uuid;
+
+use crate::context::{Context, TaskCancelled};
+use crate::locking::TaskLock;
+use crate::metrics::METRICS;
+use crate::tasks::{CronTask, RunReport};
+
+/// How many finished runs are kept around so their status can still be polled.
+const FINISHED_RUNS_TO_KEEP: usize = 256;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunTrigger {
+    Interval,
+    Manual,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", tag = "state", content = "error")]
+pub enum RunStatus {
+    Queued,
+    Running,
+    Succeeded,
+    /// Another run of the same task held the lock.
+    Skipped,
+    /// Stopped early by a shutdown, any open transaction was rolled back.
+    Cancelled,
+    Failed(String),
+}
+
+impl RunStatus {
+    fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            RunStatus::Succeeded | RunStatus::Skipped | RunStatus::Cancelled | RunStatus::Failed(_)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RunInfo {
+    pub id: Uuid,
+    pub task: &'static str,
+    pub trigger: RunTrigger,
+    pub status: RunStatus,
+    pub queued_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Set once the run finished, for a failed or cancelled run it holds what was done
+    /// until then.
+    pub report: Option<RunReport>,
+}
+
+#[derive(Debug)]
+pub enum TriggerError {
+    UnknownTask,
+    AlreadyQueued(Uuid),
+    /// The task's queue is full or its loop has stopped, e.g. during a shutdown.
+    Unavailable,
+}
+
+/// Runs every task on its interval and accepts additional manual runs.
+/// Each task is driven by a single loop, so runs of the same task never overlap.
+#[derive(Clone)]
+pub struct Scheduler {
+    triggers: Arc<HashMap<&'static str, mpsc::Sender<Uuid>>>,
+    runs: Arc<Mutex<HashMap<Uuid, RunInfo>>>,
+}
+
+impl Scheduler {
+    pub fn start(
+        context: Context,
+        tasks: Vec<Box<dyn CronTask>>,
+        join_set: &mut JoinSet<()>,
+    ) -> Self {
+        let runs = Arc::new(Mutex::new(HashMap::new()));
+        let mut triggers = HashMap::new();
+
+        for task in tasks {
+            info!("Starting task: {}", task.name());
+            // A single slot is enough, trigger() refuses to queue a second run anyway.
+            let (sender, receiver) = mpsc::channel(1);
+            triggers.insert(task.name(), sender);
+            join_set.spawn(run_task_loop(task, context.clone(), receiver, runs.clone()));
+        }
+
+        Self {
+            triggers: Arc::new(triggers),
+            runs,
+        }
+    }
+
+    /// Queues a manual run of the given task and returns its run id.
+    pub fn trigger(&self, task_name: &str) -> Result<Uuid, TriggerError> {
+        let (&name, sender) = self
+            .triggers
+            .get_key_value(task_name)
+            .ok_or(TriggerError::UnknownTask)?;
+
+        let mut runs = self.runs.lock().unwrap();
+        if let Some(pending) = runs
+            .values()
+            .find(|x| x.task == name && !x.status.is_finished())
+        {
+            return Err(TriggerError::AlreadyQueued(pending.id));
+        }
+
+        let id = Uuid::new_v4();
+        sender.try_send(id).map_err(|e| {
+            warn!("Failed to queue a manual run of task {}: {}", name, e);
+            TriggerError::Unavailable
+        })?;
+        runs.insert(id, RunInfo::new(id, name, RunTrigger::Manual));
+        info!("Queued manual run {} of task {}", id, name);
+        Ok(id)
+    }
+
+    pub fn run_info(&self, id: &Uuid) -> Option<RunInfo> {
+        self.runs.lock().unwrap().get(id).cloned()
+    }
+
+    /// Marks runs that never finished as cancelled and logs every run the shutdown interrupted.
+    pub fn log_shutdown_summary(&self) {
+        let mut runs = self.runs.lock().unwrap();
+        let now = Utc::now();
+        for run in runs.values_mut().filter(|x| !x.status.is_finished()) {
+            run.status = RunStatus::Cancelled;
+            run.finished_at = Some(now);
+        }
+
+        let interrupted = runs
+            .values()
+            .filter(|x| x.status == RunStatus::Cancelled)
+            .collect::<Vec<_>>();
+        if interrupted.is_empty() {
+            info!("Shutdown summary: no runs were interrupted");
+            return;
+        }
+
+        warn!("Shutdown summary: {} run(s) interrupted", interrupted.len());
+        for run in interrupted {
+            warn!(
+                "  {} run {} ({:?}), started at {}",
+                run.task,
+                run.id,
+                run.trigger,
+                run.started_at
+                    .map(|x| x.to_rfc3339())
+                    .unwrap_or_else(|| "never".to_string())
+            );
+        }
+    }
+}
+
+impl RunInfo {
+    fn new(id: Uuid, task: &'static str, trigger: RunTrigger) -> Self {
+        Self {
+            id,
+            task,
+            trigger,
+            status: RunStatus::Queued,
+            queued_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            report: None,
+        }
+    }
+}
+
+async fn run_task_loop(
+    task: Box<dyn CronTask>,
+    context: Context,
+    mut manual_runs: mpsc::Receiver<Uuid>,
+    runs: Arc<Mutex<HashMap<Uuid, RunInfo>>>,
+) {
+    let period = context
+        .settings
+        .task(task.name())
+        .and_then(|x| x.interval_secs)
+        .map(std::time::Duration::from_secs)
+        .unwrap_or_else(|| task.interval());
+    let mut interval = tokio::time::interval(period);
+    // A run that outlasts its interval must not be followed by a burst of catch-up runs.
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        let run_id = select! {
+            _ = interval.tick() => {
+                let id = Uuid::new_v4();
+                runs.lock()
+                    .unwrap()
+                    .insert(id, RunInfo::new(id, task.name(), RunTrigger::Interval));
+                id
+            },
+            Some(id) = manual_runs.recv() => id,
+            _ = context.cancellation.cancelled() => {
+                info!("Task {} cancelled", task.name());
+                break;
+            }
+        };
+
+        update_run(&runs, &run_id, |x| {
+            x.status = RunStatus::Running;
+            x.started_at = Some(Utc::now());
+        });
+
+        let (status, report) = match TaskLock::try_acquire(&context, task.name()).await {
+            Ok(Some(lock)) => {
+                let result = execute_run(task.as_ref(), &context, &run_id).await;
+                lock.release().await;
+                result
+            }
+            Ok(None) => (RunStatus::Skipped, None),
+            Err(e) => {
+                error!("Failed to acquire lock for task {}: {}", task.name(), e);
+                (
+                    RunStatus::Failed(format!("Failed to acquire lock: {}", e)),
+                    None,
+                )
+            }
+        };
+
+        update_run(&runs, &run_id, |x| {
+            x.status = status;
+            x.report = report;
+            x.finished_at = Some(Utc::now());
+        });
+        prune_finished_runs(&runs);
+    }
+}
+
+async fn execute_run(
+    task: &dyn CronTask,
+    context: &Context,
+    run_id: &Uuid,
+) -> (RunStatus, Option<RunReport>) {
+    let timer = METRICS
+        .task_duration
+        .with_label_values(&[task.name()])
+        .start_timer();
+    let mut report = RunReport::default();
+    let status = match task.run(context, *run_id, &mut report).await {
+        Ok(()) => {
+            debug!("Task {} completed (run {})", task.name(), run_id);
+            RunStatus::Succeeded
+        }
+        Err(e) if e.is::<TaskCancelled>() => {
+            warn!("Task {} was cancelled (run {})", task.name(), run_id);
+            RunStatus::Cancelled
+        }
+        Err(e) => {
+            METRICS
+                .task_failures
+                .with_label_values(&[task.name()])
+                .inc();
+            error!("Task {} failed (run {}): {}", task.name(), run_id, e);
+            RunStatus::Failed(e.to_string())
+        }
+    };
+    timer.observe_duration();
+    (status, Some(report))
+}
+
+fn update_run(runs: &Mutex<HashMap<Uuid, RunInfo>>, id: &Uuid, update: impl FnOnce(&mut RunInfo)) {
+    match runs.lock().unwrap().get_mut(id) {
+        Some(run) => update(run),
+        None => warn!("Run {} vanished from the run list", id),
+    }
+}
+
+fn prune_finished_runs(runs: &Mutex<HashMap<Uuid, RunInfo>>) {
+    let mut runs = runs.lock().unwrap();
+    let mut finished = runs
+        .values()
+        .filter(|x| x.status.is_finished())
+        .map(|x| (x.finished_at, x.id))
+        .collect::<Vec<_>>();
+    if finished.len() <= FINISHED_RUNS_TO_KEEP {
+        return;
+    }
+
+    finished.sort();
+    for (_, id) in &finished[..finished.len() - FINISHED_RUNS_TO_KEEP] {
+        runs.remove(id);
+    }
+}
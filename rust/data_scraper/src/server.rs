@@ -0,0 +1,135 @@
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use log::{error, info, warn};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::context::Context;
+use crate::metrics::METRICS;
+use crate::scheduler::{Scheduler, TriggerError};
+
+#[derive(Clone)]
+struct AppState {
+    context: Context,
+    scheduler: Scheduler,
+}
+
+pub async fn serve(context: Context, scheduler: Scheduler) -> Result<(), anyhow::Error> {
+    let listener = tokio::net::TcpListener::bind(&context.settings.http_listen_address)
+        .await
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to bind http server to {}: {}",
+                context.settings.http_listen_address,
+                e
+            )
+        })?;
+    info!(
+        "HTTP server listening on {}",
+        context.settings.http_listen_address
+    );
+    if context.settings.admin_token.is_none() {
+        warn!("No admin_token configured, admin endpoints are disabled");
+    }
+
+    let cancellation = context.cancellation.clone();
+    let app = Router::new()
+        .route("/metrics", get(get_metrics))
+        .route("/tasks/:name/run", post(post_task_run))
+        .route("/runs/:id", get(get_run))
+        .with_state(AppState { context, scheduler });
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(cancellation.cancelled_owned())
+        .await?;
+    Ok(())
+}
+
+async fn get_metrics() -> impl IntoResponse {
+    match METRICS.encode() {
+        Ok(body) => (StatusCode::OK, body),
+        Err(e) => {
+            error!("{}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        }
+    }
+}
+
+async fn post_task_run(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Response {
+    if let Err(rejection) = authorize(&state.context, &headers) {
+        return rejection.into_response();
+    }
+
+    match state.scheduler.trigger(&name) {
+        Ok(run_id) => (StatusCode::ACCEPTED, Json(json!({ "run_id": run_id }))).into_response(),
+        Err(TriggerError::UnknownTask) => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("Unknown task: {}", name) })),
+        )
+            .into_response(),
+        Err(TriggerError::AlreadyQueued(run_id)) => (
+            StatusCode::CONFLICT,
+            Json(json!({
+                "error": format!("Task {} is already queued or running", name),
+                "run_id": run_id
+            })),
+        )
+            .into_response(),
+        Err(TriggerError::Unavailable) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "error": format!("Task {} can't be queued right now", name) })),
+        )
+            .into_response(),
+    }
+}
+
+async fn get_run(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Response {
+    if let Err(rejection) = authorize(&state.context, &headers) {
+        return rejection.into_response();
+    }
+
+    match state.scheduler.run_info(&id) {
+        Some(run) => Json(run).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": format!("Unknown run: {}", id) })),
+        )
+            .into_response(),
+    }
+}
+
+fn authorize(context: &Context, headers: &HeaderMap) -> Result<(), (StatusCode, &'static str)> {
+    let Some(expected) = &context.settings.admin_token else {
+        return Err((StatusCode::FORBIDDEN, "Admin endpoints are disabled"));
+    };
+
+    let provided = headers
+        .get("authorization")
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "))
+        .unwrap_or_default();
+
+    if constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+        Ok(())
+    } else {
+        Err((StatusCode::UNAUTHORIZED, "Invalid admin token"))
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
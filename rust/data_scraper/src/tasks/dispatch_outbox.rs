@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+use anyhow::Error;
+use async_trait::async_trait;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::context::Context;
+use crate::outbox::dispatch_pending;
+use crate::tasks::{ConfigurableTask, CronTask, RunReport};
+
+pub struct DispatchOutboxTask {
+    config: DispatchOutboxConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DispatchOutboxConfig {
+    /// How many events one run delivers at most.
+    pub batch_size: i64,
+    /// Failed deliveries of an event before it is parked.
+    pub max_attempts: i32,
+}
+
+impl Default for DispatchOutboxConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 100,
+            max_attempts: 10,
+        }
+    }
+}
+
+impl ConfigurableTask for DispatchOutboxTask {
+    const NAME: &'static str = "DispatchOutbox";
+    type Config = DispatchOutboxConfig;
+
+    fn from_config(config: Self::Config) -> Result<Self, Error> {
+        if config.batch_size < 1 {
+            return Err(anyhow::anyhow!("batch_size must be at least 1"));
+        }
+        if config.max_attempts < 1 {
+            return Err(anyhow::anyhow!("max_attempts must be at least 1"));
+        }
+        Ok(Self { config })
+    }
+}
+
+#[async_trait]
+impl CronTask for DispatchOutboxTask {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_secs(30)
+    }
+
+    async fn run(
+        &self,
+        context: &Context,
+        _run_id: Uuid,
+        _report: &mut RunReport,
+    ) -> Result<(), Error> {
+        context.ensure_not_cancelled()?;
+        dispatch_pending(context, self.config.batch_size, self.config.max_attempts).await?;
+        Ok(())
+    }
+}
@@ -1,123 +1,891 @@
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+
 use async_trait::async_trait;
-use google_cloud_googleapis::pubsub::v1::PubsubMessage;
-use log::{info, trace};
+use chrono::{Months, NaiveDate};
+use log::{info, trace, warn};
 use serde::{Deserialize, Serialize};
-use sqlx::Executor;
+use sqlx::{Executor, PgConnection, PgPool};
+use uuid::Uuid;
 
+use crate::archive::ArtifactOrigin;
+use crate::context::{Context, TaskCancelled};
+use crate::events::{DataSource, NewDataEvent, YearMonth};
 use crate::helpers::{
-    download_derivative_information, download_derivative_to_file, get_table_of_contents,
-    load_xlsx_file, parse_range, UebernachtungenNachHerkunftslandStruct,
-    UebernachtungenProLandStruct,
+    extract_nach_herkunftsland, extract_pro_land, get_table_of_contents, headers, parse_range,
+    validate_headers, LayoutDrift, LegacyLayout, LegacyTable, LegacyTableError, RowError,
+    RowErrorPolicy, RowErrors, RowMismatch, TableOfContent, UebernachtungenNachHerkunftslandStruct,
+    UebernachtungenProLandStruct, Workbook, NACH_HERKUNFTSLAND_HEADERS, PRO_LAND_HEADERS,
+};
+use crate::metrics::METRICS;
+use crate::mods::{parse_publication, upsert_publication};
+use crate::mycore::MyCoReClient;
+use crate::outbox::enqueue_event;
+use crate::quality::{
+    check, default_quality_rules, log_violations, store_violations, MonthFigures, QualityRule,
+    QualityViolation, RowFigures,
+};
+use crate::quarantine::{
+    is_quarantined, quarantine_rows, quarantine_workbook, release_source, WorkbookSource,
 };
-use crate::tasks::CronTask;
-use crate::translate_to_month;
+use crate::tasks::{ConfigurableTask, CronTask, RunReport};
+use crate::veraenderung::{previous_year, recompute_month, stored_counts};
+use crate::{translate_from_month, translate_to_month};
+
+/// Monatsbericht Tourismus, the series this task was written for.
+pub const MONATSBERICHT_TOURISMUS_SERIES: &str = "DESerie_mods_00007671";
+
+pub struct GetSleepoverDataTask {
+    config: GetSleepoverDataConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GetSleepoverDataConfig {
+    /// Statistische Bibliothek series to scrape.
+    pub series: Vec<SeriesConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SeriesConfig {
+    /// MODS id of the series, e.g. `DESerie_mods_00007671`.
+    pub id: String,
+    /// Only used for logging.
+    pub name: String,
+    /// Which sheets of each workbook are imported, and how.
+    pub sheets: Vec<SheetMapping>,
+    /// File extensions to download from a publication, the first one found wins.
+    #[serde(default = "default_extensions")]
+    pub extensions: Vec<String>,
+    /// Layouts of older editions that don't have the sheets above, the one covering
+    /// the period of an edition is used.
+    #[serde(default)]
+    pub legacy_layouts: Vec<LegacyLayout>,
+    /// Checks run on every stored month, all of them unless configured otherwise.
+    #[serde(default = "default_quality_rules")]
+    pub quality_rules: Vec<QualityRule>,
+}
+
+fn default_extensions() -> Vec<String> {
+    vec!["xlsx".to_string(), "xls".to_string()]
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SheetMapping {
+    pub sheet: String,
+    pub parser: SheetParser,
+    #[serde(default)]
+    pub on_row_error: RowErrorPolicy,
+}
+
+/// The known sheet layouts. Each one is imported into its own table.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum SheetParser {
+    UebernachtungenNachHerkunftsland,
+    UebernachtungenProLand,
+}
+
+impl Default for GetSleepoverDataConfig {
+    fn default() -> Self {
+        Self {
+            series: vec![SeriesConfig {
+                id: MONATSBERICHT_TOURISMUS_SERIES.to_string(),
+                name: "Monatsbericht Tourismus".to_string(),
+                sheets: vec![
+                    SheetMapping {
+                        sheet: "csv-45412-07".to_string(),
+                        parser: SheetParser::UebernachtungenNachHerkunftsland,
+                        on_row_error: RowErrorPolicy::default(),
+                    },
+                    SheetMapping {
+                        sheet: "csv-45412-08".to_string(),
+                        parser: SheetParser::UebernachtungenProLand,
+                        on_row_error: RowErrorPolicy::default(),
+                    },
+                ],
+                extensions: default_extensions(),
+                legacy_layouts: vec![
+                    LegacyLayout::Monatsbericht2009,
+                    LegacyLayout::Monatsbericht2006,
+                    LegacyLayout::Monatsbericht2002,
+                ],
+                quality_rules: default_quality_rules(),
+            }],
+        }
+    }
+}
 
-pub struct GetSleepoverDataTask;
+impl ConfigurableTask for GetSleepoverDataTask {
+    const NAME: &'static str = "GetSleepoverData";
+    type Config = GetSleepoverDataConfig;
+
+    fn from_config(config: Self::Config) -> Result<Self, anyhow::Error> {
+        if config.series.is_empty() {
+            return Err(anyhow::anyhow!("series must not be empty"));
+        }
+
+        let mut ids = HashSet::new();
+        for series in &config.series {
+            if !ids.insert(&series.id) {
+                return Err(anyhow::anyhow!("Series {} is configured twice", series.id));
+            }
+            if series.sheets.is_empty() {
+                return Err(anyhow::anyhow!("Series {} has no sheets", series.id));
+            }
+            if series.extensions.is_empty() {
+                return Err(anyhow::anyhow!("Series {} has no extensions", series.id));
+            }
+            // A month is replaced table by table, two sheets can't share a table.
+            let mut parsers = HashSet::new();
+            for mapping in &series.sheets {
+                if !parsers.insert(mapping.parser) {
+                    return Err(anyhow::anyhow!(
+                        "Parser {:?} is used by more than one sheet of series {}",
+                        mapping.parser,
+                        series.id
+                    ));
+                }
+            }
+        }
+        Ok(Self { config })
+    }
+}
 
 #[async_trait]
 impl CronTask for GetSleepoverDataTask {
     fn name(&self) -> &'static str {
-        "GetSleepoverData"
+        Self::NAME
     }
 
     fn interval(&self) -> core::time::Duration {
         core::time::Duration::from_secs(60 * 60 * 12)
     }
 
-    async fn run(&self, context: &crate::context::Context) -> Result<(), anyhow::Error> {
-        let pub_sub_topic = context.pubsub_client.topic("new-data-added");
-        if !pub_sub_topic.exists(None).await? {
-            pub_sub_topic.create(None, None).await?;
+    async fn run(
+        &self,
+        context: &Context,
+        run_id: Uuid,
+        report: &mut RunReport,
+    ) -> Result<(), anyhow::Error> {
+        let mut newest_month = None;
+        let mut result = Ok(());
+        for series in &self.config.series {
+            match self
+                .ingest_series(context, series, run_id, &mut newest_month, report)
+                .await
+            {
+                Ok(()) => {}
+                Err(e) if e.is::<TaskCancelled>() => {
+                    result = Err(e);
+                    break;
+                }
+                Err(e) => {
+                    result = Err(anyhow::anyhow!("Series {}: {}", series.name, e));
+                    break;
+                }
+            }
+        }
+
+        if let Some(YearMonth { year, month }) = newest_month {
+            METRICS.set_newest_data_month(year, month);
+        }
+        result
+    }
+}
+
+impl GetSleepoverDataTask {
+    pub fn all_series(&self) -> &[SeriesConfig] {
+        &self.config.series
+    }
+
+    pub fn series(&self, id: &str) -> Option<&SeriesConfig> {
+        self.config.series.iter().find(|x| x.id == id)
+    }
+
+    async fn ingest_series(
+        &self,
+        context: &Context,
+        series: &SeriesConfig,
+        run_id: Uuid,
+        newest_month: &mut Option<YearMonth>,
+        report: &mut RunReport,
+    ) -> Result<(), anyhow::Error> {
+        let mycore = MyCoReClient::new(context);
+        let table_of_contents = context
+            .cancellable(get_table_of_contents(&mycore, &series.id))
+            .await?;
+        info!(
+            "Successfully fetched '{}' files for {}",
+            table_of_contents.len(),
+            series.name
+        );
+
+        // Every sheet is imported in the same transaction, the first table is enough.
+        let already_fetched = series.sheets[0]
+            .parser
+            .fetched_months(&context.database_client, &series.id)
+            .await?;
+        *newest_month = (*newest_month).max(already_fetched.last().copied());
+
+        for x in table_of_contents {
+            let period = YearMonth {
+                year: x.year,
+                month: x.month as u32,
+            };
+            if already_fetched.contains(&period) {
+                info!("Skipping: {} {}, we already have those.", x.year, x.month);
+                continue;
+            }
+
+            match self.ingest_month(context, series, &x, run_id, report).await {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) if e.is::<TaskCancelled>() => {
+                    warn!("Stopped before finishing {} {}", x.year, x.month);
+                    return Err(e);
+                }
+                Err(e) => return Err(e),
+            }
+            *newest_month = (*newest_month).max(Some(period));
+        }
+        Ok(())
+    }
+
+    /// Downloads and stores one month together with its new data event.
+    /// Nothing is committed if a shutdown interrupts it.
+    ///
+    /// Returns false if the workbook is quarantined, because it can't be parsed now or
+    /// couldn't be in an earlier run.
+    async fn ingest_month(
+        &self,
+        context: &Context,
+        series: &SeriesConfig,
+        entry: &TableOfContent,
+        run_id: Uuid,
+        report: &mut RunReport,
+    ) -> Result<bool, anyhow::Error> {
+        let mycore = MyCoReClient::new(context);
+        let workbook = context
+            .cancellable(async { Ok(mycore.find_file(&entry.mods_id, &series.extensions).await?) })
+            .await?;
+        trace!("{:?}", workbook);
+        if is_quarantined(&context.database_client, &workbook.md5).await? {
+            warn!(
+                "Skipping {} {}, {} is quarantined",
+                entry.year, entry.month, workbook.href
+            );
+            return Ok(false);
+        }
+
+        let period = YearMonth {
+            year: entry.year,
+            month: entry.month as u32,
+        };
+        let origin = ArtifactOrigin {
+            series_id: Some(series.id.clone()),
+            mods_id: Some(entry.mods_id.clone()),
+            period: Some(period),
+        };
+        let file = context
+            .cancellable(async { Ok(mycore.download(&workbook, origin).await?) })
+            .await?;
+
+        let source = WorkbookSource {
+            run_id,
+            series_id: &series.id,
+            mods_id: Some(&entry.mods_id),
+            url: &workbook.href,
+            md5: &workbook.md5,
+            period,
+        };
+        let parsed = match self.parse_workbook(series, &file, period).await {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                let (sheet, details) = if let Some(drift) = e.downcast_ref::<LayoutDrift>() {
+                    (
+                        Some(drift.sheet.as_str()),
+                        Some(serde_json::to_value(drift)?),
+                    )
+                } else if let Some(errors) = e.downcast_ref::<RowErrors>() {
+                    (
+                        Some(errors.sheet.as_str()),
+                        Some(serde_json::to_value(errors)?),
+                    )
+                } else if let Some(error) = e.downcast_ref::<LegacyTableError>() {
+                    (
+                        Some(error.sheet.as_str()),
+                        Some(serde_json::to_value(error)?),
+                    )
+                } else if e.is::<UnknownLayout>() {
+                    (None, None)
+                } else {
+                    // Anything else, e.g. a file that can't be read, may work next time.
+                    return Err(e);
+                };
+                quarantine_workbook(
+                    &context.database_client,
+                    &source,
+                    sheet,
+                    &e.to_string(),
+                    details,
+                )
+                .await?;
+                return Ok(false);
+            }
+        };
+
+        let publication_id = self
+            .record_publication(context, series, &mycore, entry, period)
+            .await;
+
+        let violations = self
+            .store_month(context, series, &source, parsed, publication_id.as_deref())
+            .await?;
+        report.add_quality_violations(&violations);
+        Ok(true)
+    }
+
+    /// Stores the MODS metadata of the publication and returns its id. Rows are still
+    /// imported if that fails, they just aren't linked to a publication.
+    async fn record_publication(
+        &self,
+        context: &Context,
+        series: &SeriesConfig,
+        mycore: &MyCoReClient<'_>,
+        entry: &TableOfContent,
+        period: YearMonth,
+    ) -> Option<String> {
+        let result = async {
+            let xml = context
+                .cancellable(async { Ok(mycore.object(&entry.mods_id).await?) })
+                .await?;
+            let mut publication = parse_publication(&entry.mods_id, &xml)?;
+            if publication.reference_period_start.is_none() {
+                publication.reference_period_start =
+                    NaiveDate::from_ymd_opt(period.year, period.month, 1);
+                publication.reference_period_end = publication
+                    .reference_period_start
+                    .and_then(|x| x.checked_add_months(Months::new(1)))
+                    .and_then(|x| x.pred_opt());
+            }
+            upsert_publication(&context.database_client, &series.id, &publication).await?;
+            Ok::<_, anyhow::Error>(publication.mods_id)
+        }
+        .await;
+
+        match result {
+            Ok(mods_id) => Some(mods_id),
+            Err(e) => {
+                warn!("Failed to record publication {}: {}", entry.mods_id, e);
+                None
+            }
+        }
+    }
+
+    /// Parses a workbook of the series and replaces the rows of its month with its content.
+    /// Returns the data quality violations of the month.
+    pub async fn import_workbook(
+        &self,
+        context: &Context,
+        series: &SeriesConfig,
+        file: &File,
+        source: &WorkbookSource<'_>,
+        publication_id: Option<&str>,
+    ) -> Result<Vec<QualityViolation>, anyhow::Error> {
+        let parsed = self.parse_workbook(series, file, source.period).await?;
+        self.store_month(context, series, source, parsed, publication_id)
+            .await
+    }
+
+    /// Replaces the rows of the month with the parsed sheets, quarantines the skipped
+    /// rows, checks the data quality and enqueues the event. Earlier quarantine entries
+    /// of the workbook are released, it was imported after all.
+    async fn store_month(
+        &self,
+        context: &Context,
+        series: &SeriesConfig,
+        source: &WorkbookSource<'_>,
+        parsed: ParsedWorkbook,
+        publication_id: Option<&str>,
+    ) -> Result<Vec<QualityViolation>, anyhow::Error> {
+        let period = source.period;
+        let mut event = NewDataEvent::new(DataSource::Sleepover, source.run_id);
+        event.months.insert(period);
+
+        for (sheet, parsed) in &parsed.sheets {
+            info!("{:?}", parsed.len());
+            METRICS
+                .rows_parsed
+                .with_label_values(&[sheet])
+                .inc_by(parsed.len() as u64);
+            event.laender.extend(parsed.laender());
+            event.add_rows(parsed.table(), parsed.len() as u64);
+        }
+        let parsed_sheets = parsed
+            .sheets
+            .into_iter()
+            .map(|(_, parsed)| parsed)
+            .collect::<Vec<_>>();
+
+        let mut tx = context.database_client.begin().await?;
+        for parsed in &parsed_sheets {
+            parsed
+                .replace_month(context, &mut tx, source.series_id, period, publication_id)
+                .await?;
+        }
+        quarantine_rows(&mut tx, source, &parsed.skipped_rows).await?;
+
+        // The month is also the base of the same month a year later, if that is stored.
+        let following = YearMonth {
+            year: period.year + 1,
+            month: period.month,
+        };
+        for parsed in &parsed_sheets {
+            let recomputed =
+                recompute_month(&mut tx, parsed.parser(), source.series_id, period).await?;
+            info!(
+                "Recomputed the changes of {}: {} revised, {} filled",
+                parsed.table(),
+                recomputed.revised,
+                recomputed.filled
+            );
+            if recompute_month(&mut tx, parsed.parser(), source.series_id, following)
+                .await?
+                .rows
+                > 0
+            {
+                event.months.insert(following);
+            }
+        }
+
+        let mut violations = Vec::new();
+        for parsed in &parsed_sheets {
+            let month = MonthFigures {
+                table: parsed.table(),
+                period,
+                rows: parsed.figures(),
+                previous_year: stored_counts(
+                    &mut tx,
+                    parsed.parser(),
+                    source.series_id,
+                    previous_year(period),
+                )
+                .await?,
+            };
+            let found = check(&series.quality_rules, &month);
+            store_violations(
+                &mut tx,
+                source.run_id,
+                source.series_id,
+                parsed.table(),
+                period,
+                &found,
+            )
+            .await?;
+            violations.extend(found);
+        }
+        let released = release_source(&mut tx, source.md5, source.run_id).await?;
+        if released > 0 {
+            info!("Released {} quarantine entries of {}", released, source.url);
+        }
+
+        enqueue_event(&mut tx, &context.settings.new_data_topic, &event).await?;
+
+        if context.cancellation.is_cancelled() {
+            tx.rollback().await?;
+            info!(
+                "Rolled back {} {} because of shutdown",
+                period.year, period.month
+            );
+            return Err(TaskCancelled.into());
+        }
+
+        tx.commit().await?;
+        for parsed in &parsed_sheets {
+            METRICS
+                .rows_inserted
+                .with_label_values(&[parsed.table()])
+                .inc_by(parsed.len() as u64);
+        }
+        log_violations(&violations);
+        Ok(violations)
+    }
+
+    /// Parses the configured sheets or, if the workbook doesn't have them, the tables
+    /// of the legacy layout of the period. Returns the parsed sheets by sheet name.
+    async fn parse_workbook(
+        &self,
+        series: &SeriesConfig,
+        file: &File,
+        period: YearMonth,
+    ) -> Result<ParsedWorkbook, anyhow::Error> {
+        let mut workbook = Workbook::open(file)?;
+        let sheet_names = series
+            .sheets
+            .iter()
+            .map(|x| x.sheet.as_str())
+            .collect::<Vec<_>>();
+
+        let mut parsed_sheets = Vec::new();
+        let mut skipped_rows = Vec::new();
+        if workbook.has_sheets(&sheet_names) {
+            let mut ranges = workbook.load_sheets(&sheet_names)?;
+            for mapping in &series.sheets {
+                let range = ranges
+                    .remove(&mapping.sheet)
+                    .ok_or(anyhow::anyhow!("Sheet {} not loaded", mapping.sheet))?;
+                let (parsed, skipped) =
+                    mapping
+                        .parser
+                        .parse(&mapping.sheet, range, mapping.on_row_error, period)?;
+                parsed_sheets.push((mapping.sheet.clone(), parsed));
+                skipped_rows.extend(skipped);
+            }
+            return Ok(ParsedWorkbook {
+                sheets: parsed_sheets,
+                skipped_rows,
+            });
+        }
+
+        // Older editions have sheets with the same names holding other tables, so the
+        // layout goes by the period and the sheet names are only checked.
+        let Some(layout) = series.legacy_layouts.iter().find(|x| x.covers(period)) else {
+            return Err(UnknownLayout(format!(
+                "The {} workbook has neither the sheets {} nor a legacy layout for {}-{:02}",
+                workbook.format,
+                sheet_names.join(", "),
+                period.year,
+                period.month
+            ))
+            .into());
+        };
+        if !workbook.has_sheets(&layout.sheet_names()) {
+            return Err(UnknownLayout(format!(
+                "The {} workbook has neither the sheets {} nor the sheets {} of the legacy layout {:?}",
+                workbook.format,
+                sheet_names.join(", "),
+                layout.sheet_names().join(", "),
+                layout
+            ))
+            .into());
+        }
+        info!(
+            "Reading {} workbook with the legacy layout {:?}",
+            workbook.format, layout
+        );
+        for mapping in &series.sheets {
+            let table = mapping.parser.legacy_table(*layout);
+            let range = workbook
+                .load_sheets(&[table.sheet])?
+                .remove(table.sheet)
+                .ok_or(anyhow::anyhow!("Sheet {} not loaded", table.sheet))?;
+            let parsed = match mapping.parser {
+                SheetParser::UebernachtungenNachHerkunftsland => {
+                    ParsedSheet::UebernachtungenNachHerkunftsland(extract_nach_herkunftsland(
+                        &range, table, period,
+                    )?)
+                }
+                SheetParser::UebernachtungenProLand => {
+                    ParsedSheet::UebernachtungenProLand(extract_pro_land(&range, table, period)?)
+                }
+            };
+            parsed_sheets.push((table.sheet.to_string(), parsed));
+        }
+        Ok(ParsedWorkbook {
+            sheets: parsed_sheets,
+            skipped_rows,
+        })
+    }
+}
+
+fn in_period(period: YearMonth, jahr: i64, monat: &str) -> Result<(), RowMismatch> {
+    let mismatch = |column, found: String| RowMismatch {
+        column,
+        message: format!(
+            "Row of {} in the edition of {}-{:02}",
+            found, period.year, period.month
+        ),
+    };
+    if jahr != period.year as i64 {
+        return Err(mismatch("Jahr", jahr.to_string()));
+    }
+    if translate_from_month(monat) != Some(period.month as i32) {
+        return Err(mismatch("Monat", monat.trim().to_string()));
+    }
+    Ok(())
+}
+
+/// The workbook has neither the configured sheets nor the ones of a legacy layout.
+#[derive(Debug)]
+struct UnknownLayout(String);
+
+impl Display for UnknownLayout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownLayout {}
+
+/// The parsed sheets by sheet name, and the rows left out of them.
+pub struct ParsedWorkbook {
+    sheets: Vec<(String, ParsedSheet)>,
+    skipped_rows: Vec<RowError>,
+}
+
+pub enum ParsedSheet {
+    UebernachtungenNachHerkunftsland(Vec<UebernachtungenNachHerkunftslandStruct>),
+    UebernachtungenProLand(Vec<UebernachtungenProLandStruct>),
+}
+
+impl SheetParser {
+    pub fn table(&self) -> &'static str {
+        match self {
+            SheetParser::UebernachtungenNachHerkunftsland => "uebernachtungen_nach_herkunftsland",
+            SheetParser::UebernachtungenProLand => "uebernachtungen_pro_land",
+        }
+    }
+
+    fn legacy_table(&self, layout: LegacyLayout) -> LegacyTable {
+        match self {
+            SheetParser::UebernachtungenNachHerkunftsland => layout.herkunftsland(),
+            SheetParser::UebernachtungenProLand => layout.pro_land(),
         }
+    }
 
-        let publisher = pub_sub_topic.new_publisher(None);
+    pub fn expected_headers(&self) -> &'static [&'static str] {
+        match self {
+            SheetParser::UebernachtungenNachHerkunftsland => &NACH_HERKUNFTSLAND_HEADERS,
+            SheetParser::UebernachtungenProLand => &PRO_LAND_HEADERS,
+        }
+    }
 
-        let table_of_contents = get_table_of_contents(&context.http_client).await?;
-        let mut new_data_added = false;
-        info!("Successfully fetched '{}' files", table_of_contents.len());
+    /// Returns the sheet and the rows that were skipped. Rows of another month than
+    /// the period of the edition are row errors, the month is replaced as a whole.
+    fn parse(
+        &self,
+        sheet: &str,
+        range: calamine::Range<calamine::Data>,
+        policy: RowErrorPolicy,
+        period: YearMonth,
+    ) -> Result<(ParsedSheet, Vec<RowError>), anyhow::Error> {
+        validate_headers(sheet, &headers(&range), self.expected_headers())?;
+        Ok(match self {
+            SheetParser::UebernachtungenNachHerkunftsland => {
+                let (rows, skipped) = parse_range(
+                    sheet,
+                    &range,
+                    policy,
+                    |x: &UebernachtungenNachHerkunftslandStruct| {
+                        in_period(period, x.jahr, &x.monat)
+                    },
+                )?;
+                (ParsedSheet::UebernachtungenNachHerkunftsland(rows), skipped)
+            }
+            SheetParser::UebernachtungenProLand => {
+                let (rows, skipped) =
+                    parse_range(sheet, &range, policy, |x: &UebernachtungenProLandStruct| {
+                        in_period(period, x.jahr, &x.monat)
+                    })?;
+                (ParsedSheet::UebernachtungenProLand(rows), skipped)
+            }
+        })
+    }
 
+    /// Months of the series that already have rows in the table of this parser.
+    pub async fn fetched_months(
+        &self,
+        database: &PgPool,
+        series_id: &str,
+    ) -> Result<BTreeSet<YearMonth>, anyhow::Error> {
         #[derive(Debug, Serialize, Deserialize)]
         struct Date {
             jahr: i64,
             monat: String,
         }
-        let already_fetched_dates: Vec<Date> =
-            sqlx::query_file_as!(Date, "src/queries/select_already_fetched_dates.sql")
-                .fetch_all(&context.database_client)
-                .await?;
+        let dates: Vec<Date> = match self {
+            SheetParser::UebernachtungenNachHerkunftsland => {
+                sqlx::query_file_as!(
+                    Date,
+                    "src/queries/select_already_fetched_dates_nach_herkunftsland.sql",
+                    series_id
+                )
+                .fetch_all(database)
+                .await?
+            }
+            SheetParser::UebernachtungenProLand => {
+                sqlx::query_file_as!(
+                    Date,
+                    "src/queries/select_already_fetched_dates.sql",
+                    series_id
+                )
+                .fetch_all(database)
+                .await?
+            }
+        };
 
-        for x in table_of_contents {
-            if already_fetched_dates
-                .iter()
-                .any(|y| y.jahr == x.year as i64 && y.monat == translate_to_month(x.month))
-            {
-                info!("Skipping: {} {}, we already have those.", x.year, x.month);
-                continue;
+        Ok(dates
+            .iter()
+            .filter_map(|x| {
+                Some(YearMonth {
+                    year: x.jahr as i32,
+                    month: translate_from_month(&x.monat)? as u32,
+                })
+            })
+            .collect())
+    }
+}
+
+impl ParsedSheet {
+    fn len(&self) -> usize {
+        match self {
+            ParsedSheet::UebernachtungenNachHerkunftsland(rows) => rows.len(),
+            ParsedSheet::UebernachtungenProLand(rows) => rows.len(),
+        }
+    }
+
+    fn parser(&self) -> SheetParser {
+        match self {
+            ParsedSheet::UebernachtungenNachHerkunftsland(_) => {
+                SheetParser::UebernachtungenNachHerkunftsland
             }
+            ParsedSheet::UebernachtungenProLand(_) => SheetParser::UebernachtungenProLand,
+        }
+    }
+
+    fn table(&self) -> &'static str {
+        self.parser().table()
+    }
 
-            new_data_added = true;
-
-            let derivative_info =
-                download_derivative_information(&context.http_client, &x.mods_id).await?;
-            trace!("{:?}", derivative_info);
-
-            let file =
-                download_derivative_to_file(&context.http_client, &derivative_info.children[0])
-                    .await?;
-
-            let parsed_file = load_xlsx_file(&file).await?;
-            let overnight_by_origin: Vec<UebernachtungenNachHerkunftslandStruct> =
-                parse_range(parsed_file.uebernachtungen_nach_herkunftsland).await?;
-            let overnight_by_country: Vec<UebernachtungenProLandStruct> =
-                parse_range(parsed_file.uebernachtungen_pro_land).await?;
-
-            info!("{:?}", overnight_by_origin.len());
-            info!("{:?}", overnight_by_country.len());
-
-            let mut tx = context.database_client.begin().await?;
-            for x in overnight_by_origin {
-                let query = sqlx::query_file!(
-                    "src/queries/insert_into_uebernachtungen_nach_herkunftsland.sql",
-                    x.herkunftsregion.trim(),
-                    x.jahr,
-                    x.monat.trim(),
-                    x.ankuenfte_anzahl,
-                    x.ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent,
-                    x.uebernachtungen_anzahl,
-                    x.uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent,
-                    x.durchsch_aufenthaltsdauer_tage
-                );
-                tx.execute(query).await?;
-            }
-
-            for x in overnight_by_country {
-                let query = sqlx::query_file!(
-                    "src/queries/insert_into_uebernachtungen_pro_land.sql",
-                    x.land.trim(),
-                    x.wohnsitz.trim(),
-                    x.jahr,
-                    x.monat.trim(),
-                    x.ankuenfte_anzahl,
-                    x.ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent,
-                    x.uebernachtungen_anzahl,
-                    x.uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent,
-                    x.durchsch_aufenthaltsdauer_tage
-                );
-                tx.execute(query).await?;
-            }
-
-            tx.commit().await?;
-        }
-
-        if new_data_added {
-            let awaiter = publisher
-                .publish(PubsubMessage {
-                    data: b"new data added".to_vec(),
-                    ..Default::default()
+    fn figures(&self) -> Vec<RowFigures<'_>> {
+        match self {
+            ParsedSheet::UebernachtungenNachHerkunftsland(rows) => rows
+                .iter()
+                .map(|x| RowFigures {
+                    land: None,
+                    herkunft: x.herkunftsregion.trim(),
+                    ankuenfte: x.ankuenfte_anzahl,
+                    ankuenfte_veraenderung: x.ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent,
+                    uebernachtungen: x.uebernachtungen_anzahl,
+                    uebernachtungen_veraenderung: x
+                        .uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent,
+                    aufenthaltsdauer: x.durchsch_aufenthaltsdauer_tage,
+                })
+                .collect(),
+            ParsedSheet::UebernachtungenProLand(rows) => rows
+                .iter()
+                .map(|x| RowFigures {
+                    land: Some(x.land.trim()),
+                    herkunft: x.wohnsitz.trim(),
+                    ankuenfte: x.ankuenfte_anzahl,
+                    ankuenfte_veraenderung: x.ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent,
+                    uebernachtungen: x.uebernachtungen_anzahl,
+                    uebernachtungen_veraenderung: x
+                        .uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent,
+                    aufenthaltsdauer: x.durchsch_aufenthaltsdauer_tage,
                 })
-                .await;
-            awaiter.get().await?;
-            info!("Published new data added message to pubsub")
+                .collect(),
+        }
+    }
+
+    fn laender(&self) -> BTreeSet<String> {
+        match self {
+            ParsedSheet::UebernachtungenNachHerkunftsland(_) => BTreeSet::new(),
+            ParsedSheet::UebernachtungenProLand(rows) => {
+                rows.iter().map(|x| x.land.trim().to_string()).collect()
+            }
+        }
+    }
+
+    /// Deletes the month of the series from the table and inserts the parsed rows. The
+    /// delete only matters when reprocessing, new months have no rows yet.
+    async fn replace_month(
+        &self,
+        context: &Context,
+        tx: &mut PgConnection,
+        series_id: &str,
+        period: YearMonth,
+        publication_id: Option<&str>,
+    ) -> Result<(), anyhow::Error> {
+        let monat = translate_to_month(period.month as i32);
+        match self {
+            ParsedSheet::UebernachtungenNachHerkunftsland(rows) => {
+                tx.execute(sqlx::query_file!(
+                    "src/queries/delete_uebernachtungen_nach_herkunftsland_for_month.sql",
+                    period.year as i64,
+                    monat,
+                    series_id
+                ))
+                .await?;
+                for x in rows {
+                    if context.cancellation.is_cancelled() {
+                        break;
+                    }
+                    let query = sqlx::query_file!(
+                        "src/queries/insert_into_uebernachtungen_nach_herkunftsland.sql",
+                        x.herkunftsregion.trim(),
+                        x.jahr,
+                        x.monat.trim(),
+                        x.ankuenfte_anzahl.value,
+                        x.ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent.value,
+                        x.uebernachtungen_anzahl.value,
+                        x.uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent
+                            .value,
+                        x.durchsch_aufenthaltsdauer_tage.value,
+                        publication_id,
+                        x.ankuenfte_anzahl.flag_code(),
+                        x.ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent
+                            .flag_code(),
+                        x.uebernachtungen_anzahl.flag_code(),
+                        x.uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent
+                            .flag_code(),
+                        x.durchsch_aufenthaltsdauer_tage.flag_code(),
+                        series_id
+                    );
+                    tx.execute(query).await?;
+                }
+            }
+            ParsedSheet::UebernachtungenProLand(rows) => {
+                tx.execute(sqlx::query_file!(
+                    "src/queries/delete_uebernachtungen_pro_land_for_month.sql",
+                    period.year as i64,
+                    monat,
+                    series_id
+                ))
+                .await?;
+                for x in rows {
+                    if context.cancellation.is_cancelled() {
+                        break;
+                    }
+                    let query = sqlx::query_file!(
+                        "src/queries/insert_into_uebernachtungen_pro_land.sql",
+                        x.land.trim(),
+                        x.wohnsitz.trim(),
+                        x.jahr,
+                        x.monat.trim(),
+                        x.ankuenfte_anzahl.value,
+                        x.ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent.value,
+                        x.uebernachtungen_anzahl.value,
+                        x.uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent
+                            .value,
+                        x.durchsch_aufenthaltsdauer_tage.value,
+                        publication_id,
+                        x.ankuenfte_anzahl.flag_code(),
+                        x.ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent
+                            .flag_code(),
+                        x.uebernachtungen_anzahl.flag_code(),
+                        x.uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent
+                            .flag_code(),
+                        x.durchsch_aufenthaltsdauer_tage.flag_code(),
+                        series_id
+                    );
+                    tx.execute(query).await?;
+                }
+            }
         }
         Ok(())
     }
@@ -1,10 +1,22 @@
+use std::collections::BTreeMap;
+
 use async_trait::async_trait;
+use log::info;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use uuid::Uuid;
 
-pub use get_sleepover_data::GetSleepoverDataTask;
+pub use dispatch_outbox::DispatchOutboxTask;
+pub use get_sleepover_data::{
+    GetSleepoverDataTask, SeriesConfig, SheetParser, MONATSBERICHT_TOURISMUS_SERIES,
+};
 pub use start_ferien_update::StartFerienUpdateTask;
 
 use crate::context::Context;
+use crate::quality::QualityViolation;
+use crate::settings::{to_snake_case, Settings};
 
+mod dispatch_outbox;
 mod get_sleepover_data;
 mod start_ferien_update;
 
@@ -14,7 +26,33 @@ pub trait CronTask: Send + Sync {
 
     fn interval(&self) -> core::time::Duration;
 
-    async fn run(&self, context: &Context) -> Result<(), anyhow::Error>;
+    /// Adds what the run finds to the report as it goes, so a run that fails halfway
+    /// still reports its first part.
+    async fn run(
+        &self,
+        context: &Context,
+        run_id: Uuid,
+        report: &mut RunReport,
+    ) -> Result<(), anyhow::Error>;
+}
+
+/// What a run found besides its status, shown in the run ledger.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RunReport {
+    /// Data quality violations by rule, the violations themselves are stored with
+    /// the run id.
+    pub quality_violations: BTreeMap<String, u64>,
+}
+
+impl RunReport {
+    pub fn add_quality_violations(&mut self, violations: &[QualityViolation]) {
+        for x in violations {
+            *self
+                .quality_violations
+                .entry(x.rule.to_string())
+                .or_default() += 1;
+        }
+    }
 }
 
 pub trait CronTaskExtension {
@@ -25,3 +63,104 @@ impl<C: CronTask + Sized> CronTaskExtension for C {
         Box::new(self)
     }
 }
+
+/// A task that is built from the `params` of its section in the settings.
+pub trait ConfigurableTask: CronTask + Sized + 'static {
+    /// Name of the task, its section in the settings is the snake case of it.
+    const NAME: &'static str;
+
+    /// Used when the settings don't contain any params for the task.
+    type Config: DeserializeOwned + Default;
+
+    fn from_config(config: Self::Config) -> Result<Self, anyhow::Error>;
+}
+
+struct TaskRegistration {
+    name: &'static str,
+    build: fn(&serde_json::Value) -> Result<Box<dyn CronTask>, anyhow::Error>,
+}
+
+/// Every task the scheduler knows about.
+fn registry() -> Vec<TaskRegistration> {
+    vec![
+        register::<GetSleepoverDataTask>(),
+        register::<StartFerienUpdateTask>(),
+        register::<DispatchOutboxTask>(),
+    ]
+}
+
+fn register<T: ConfigurableTask>() -> TaskRegistration {
+    TaskRegistration {
+        name: T::NAME,
+        build: |params| Ok(from_params::<T>(params)?.into_boxed()),
+    }
+}
+
+fn from_params<T: ConfigurableTask>(params: &serde_json::Value) -> Result<T, anyhow::Error> {
+    let config = if params.is_null() {
+        T::Config::default()
+    } else {
+        serde_json::from_value(params.clone())?
+    };
+    T::from_config(config)
+}
+
+/// Builds a single task from its section in the settings, whether it is enabled or not.
+pub fn configured_task<T: ConfigurableTask>(settings: &Settings) -> Result<T, anyhow::Error> {
+    let params = settings
+        .task(T::NAME)
+        .map(|x| x.params.clone())
+        .unwrap_or_default();
+    from_params(&params).map_err(|e| {
+        anyhow::anyhow!(
+            "Invalid settings for task {}: {}",
+            to_snake_case(T::NAME),
+            e
+        )
+    })
+}
+
+/// Builds all enabled tasks. Sections for tasks that don't exist are an error, they are
+/// most likely typos that would otherwise silently keep a task at its defaults.
+pub fn tasks_from_settings(settings: &Settings) -> Result<Vec<Box<dyn CronTask>>, anyhow::Error> {
+    let registry = registry();
+
+    let mut unknown = settings
+        .tasks
+        .keys()
+        .filter(|key| !registry.iter().any(|x| to_snake_case(x.name) == **key))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !unknown.is_empty() {
+        unknown.sort();
+        return Err(anyhow::anyhow!(
+            "Unknown task(s) in settings: {}. Known tasks are: {}",
+            unknown.join(", "),
+            registry
+                .iter()
+                .map(|x| to_snake_case(x.name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    let mut tasks = Vec::new();
+    for registration in registry {
+        let task_settings = settings.task(registration.name);
+        if task_settings.is_some_and(|x| !x.enabled) {
+            info!("Task {} is disabled", registration.name);
+            continue;
+        }
+
+        let params = task_settings.map(|x| x.params.clone()).unwrap_or_default();
+        let task = (registration.build)(&params).map_err(|e| {
+            anyhow::anyhow!(
+                "Invalid settings for task {}: {}",
+                to_snake_case(registration.name),
+                e
+            )
+        })?;
+        tasks.push(task);
+    }
+    Ok(tasks)
+}
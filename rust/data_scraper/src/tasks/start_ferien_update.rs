@@ -2,38 +2,76 @@ use std::time::Duration;
 
 use anyhow::Error;
 use async_trait::async_trait;
-use google_cloud_googleapis::pubsub::v1::PubsubMessage;
+use chrono::Datelike;
+use serde::Deserialize;
+use uuid::Uuid;
 
 use crate::context::Context;
-use crate::tasks::CronTask;
+use crate::events::{DataSource, NewDataEvent, YearMonth};
+use crate::outbox::enqueue_event;
+use crate::tasks::{ConfigurableTask, CronTask, RunReport};
 
-pub struct StartFerienUpdateTask;
+pub struct StartFerienUpdateTask {
+    config: StartFerienUpdateConfig,
+}
+
+/// Years whose school holidays should be refreshed. Unset bounds default to the
+/// current year and the year after it.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StartFerienUpdateConfig {
+    pub first_year: Option<i32>,
+    pub last_year: Option<i32>,
+}
+
+impl ConfigurableTask for StartFerienUpdateTask {
+    const NAME: &'static str = "StartFerienUpdate";
+    type Config = StartFerienUpdateConfig;
+
+    fn from_config(config: Self::Config) -> Result<Self, Error> {
+        if let (Some(first_year), Some(last_year)) = (config.first_year, config.last_year) {
+            if first_year > last_year {
+                return Err(anyhow::anyhow!(
+                    "first_year {} is after last_year {}",
+                    first_year,
+                    last_year
+                ));
+            }
+        }
+        Ok(Self { config })
+    }
+}
 
 #[async_trait]
 impl CronTask for StartFerienUpdateTask {
     fn name(&self) -> &'static str {
-        "StartFerienUpdate"
+        Self::NAME
     }
 
     fn interval(&self) -> Duration {
         Duration::from_secs(7 * 24 * 60 * 60)
     }
 
-    async fn run(&self, context: &Context) -> Result<(), Error> {
-        let pub_sub_topic = context.pubsub_client.topic("new-data-added");
-        if !pub_sub_topic.exists(None).await? {
-            pub_sub_topic.create(None, None).await?;
+    async fn run(
+        &self,
+        context: &Context,
+        run_id: Uuid,
+        _report: &mut RunReport,
+    ) -> Result<(), Error> {
+        context.ensure_not_cancelled()?;
+        let current_year = chrono::Utc::now().year();
+        let first_year = self.config.first_year.unwrap_or(current_year);
+        let last_year = self.config.last_year.unwrap_or(current_year + 1);
+
+        let mut event = NewDataEvent::new(DataSource::Ferien, run_id);
+        for year in first_year..=last_year {
+            event
+                .months
+                .extend((1..=12).map(|month| YearMonth { year, month }));
         }
-        let publisher = pub_sub_topic.new_publisher(None);
-
-        let awaiter = publisher
-            .clone()
-            .publish(PubsubMessage {
-                data: b"new data added".to_vec(),
-                ..Default::default()
-            })
-            .await;
-        awaiter.get().await?;
+
+        let mut connection = context.database_client.acquire().await?;
+        enqueue_event(&mut connection, &context.settings.new_data_topic, &event).await?;
 
         Ok(())
     }
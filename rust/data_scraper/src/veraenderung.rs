@@ -0,0 +1,475 @@
+//! Changes to the same month of the previous year, recomputed from the stored counts.
+//!
+//! Destatis computes its changes against the previous year as it was known when the
+//! edition came out, so a published change that doesn't match the stored counts
+//! usually means those were revised since. Where only a placeholder was published,
+//! the recomputed change fills the gap. The published value is never overwritten,
+//! both are stored side by side.
+
+use std::collections::HashMap;
+
+use log::info;
+use sqlx::PgConnection;
+
+use crate::context::Context;
+use crate::events::YearMonth;
+use crate::tasks::{configured_task, GetSleepoverDataTask, SheetParser};
+use crate::translate_to_month;
+
+/// Changes are published with one decimal.
+pub const TOLERANCE: f64 = 0.1;
+
+/// How the published change relates to the recomputed one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Abgleich {
+    /// Both agree within `TOLERANCE`.
+    Match,
+    /// They differ, the counts of the previous year were most likely revised.
+    Revised,
+    /// Nothing was published, only the recomputed change is there.
+    Filled,
+}
+
+impl Abgleich {
+    /// Stored in the `*_veraenderung_abgleich` column.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Abgleich::Match => "match",
+            Abgleich::Revised => "revised",
+            Abgleich::Filled => "filled",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Recomputed {
+    pub value: Option<f64>,
+    pub abgleich: Option<Abgleich>,
+}
+
+impl Recomputed {
+    pub fn new(current: Option<i64>, previous: Option<i64>, published: Option<f64>) -> Self {
+        let Some(value) = change_percent(current, previous) else {
+            return Self::default();
+        };
+        let abgleich = match published {
+            None => Abgleich::Filled,
+            Some(published) if (published - value).abs() <= TOLERANCE => Abgleich::Match,
+            Some(_) => Abgleich::Revised,
+        };
+        Self {
+            value: Some(value),
+            abgleich: Some(abgleich),
+        }
+    }
+
+    fn abgleich_code(&self) -> Option<&'static str> {
+        self.abgleich.map(|x| x.code())
+    }
+}
+
+/// The change to the previous year in percent, `None` if there is nothing to compare.
+pub fn change_percent(current: Option<i64>, previous: Option<i64>) -> Option<f64> {
+    match (current, previous) {
+        (Some(current), Some(previous)) if previous > 0 => {
+            Some((current as f64 / previous as f64 - 1.0) * 100.0)
+        }
+        _ => None,
+    }
+}
+
+/// Stored counts of the same month one year earlier, by land and herkunft.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PreviousYear {
+    pub ankuenfte: Option<i64>,
+    pub uebernachtungen: Option<i64>,
+}
+
+pub type PreviousYearRows = HashMap<(Option<String>, String), PreviousYear>;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RecomputeSummary {
+    pub rows: u64,
+    pub revised: u64,
+    pub filled: u64,
+}
+
+impl RecomputeSummary {
+    fn add(&mut self, other: RecomputeSummary) {
+        self.rows += other.rows;
+        self.revised += other.revised;
+        self.filled += other.filled;
+    }
+
+    fn count(&mut self, row: &[Recomputed]) {
+        self.rows += 1;
+        for x in row {
+            match x.abgleich {
+                Some(Abgleich::Revised) => self.revised += 1,
+                Some(Abgleich::Filled) => self.filled += 1,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// A stored row, `land` and `herkunft` as they are in the table.
+struct StoredRow {
+    land: Option<String>,
+    herkunft: String,
+    ankuenfte: Option<i64>,
+    ankuenfte_veraenderung: Option<f64>,
+    uebernachtungen: Option<i64>,
+    uebernachtungen_veraenderung: Option<f64>,
+}
+
+impl StoredRow {
+    fn key(&self) -> (Option<String>, String) {
+        (
+            self.land.as_deref().map(|x| x.trim().to_string()),
+            self.herkunft.trim().to_string(),
+        )
+    }
+
+    /// The changes of ankuenfte and uebernachtungen against the previous year.
+    fn recompute(&self, previous: &PreviousYearRows) -> [Recomputed; 2] {
+        let previous = previous.get(&self.key());
+        [
+            Recomputed::new(
+                self.ankuenfte,
+                previous.and_then(|x| x.ankuenfte),
+                self.ankuenfte_veraenderung,
+            ),
+            Recomputed::new(
+                self.uebernachtungen,
+                previous.and_then(|x| x.uebernachtungen),
+                self.uebernachtungen_veraenderung,
+            ),
+        ]
+    }
+}
+
+async fn stored_rows(
+    tx: &mut PgConnection,
+    parser: SheetParser,
+    series_id: &str,
+    period: YearMonth,
+) -> Result<Vec<StoredRow>, anyhow::Error> {
+    let jahr = period.year as i64;
+    let monat = translate_to_month(period.month as i32);
+    Ok(match parser {
+        SheetParser::UebernachtungenNachHerkunftsland => sqlx::query_file!(
+            "src/queries/select_uebernachtungen_nach_herkunftsland_for_month.sql",
+            jahr,
+            monat,
+            series_id
+        )
+        .fetch_all(tx)
+        .await?
+        .into_iter()
+        .filter_map(|x| {
+            Some(StoredRow {
+                land: None,
+                herkunft: x.herkunftsregion?,
+                ankuenfte: x.ankuenfte_anzahl,
+                ankuenfte_veraenderung: x.ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent,
+                uebernachtungen: x.uebernachtungen_anzahl,
+                uebernachtungen_veraenderung: x
+                    .uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent,
+            })
+        })
+        .collect(),
+        SheetParser::UebernachtungenProLand => sqlx::query_file!(
+            "src/queries/select_uebernachtungen_pro_land_for_month.sql",
+            jahr,
+            monat,
+            series_id
+        )
+        .fetch_all(tx)
+        .await?
+        .into_iter()
+        .filter_map(|x| {
+            Some(StoredRow {
+                land: Some(x.land?),
+                herkunft: x.wohnsitz?,
+                ankuenfte: x.ankuenfte_anzahl,
+                ankuenfte_veraenderung: x.ankuenfte_veraenderung_zum_vorjahreszeitraum_prozent,
+                uebernachtungen: x.uebernachtungen_anzahl,
+                uebernachtungen_veraenderung: x
+                    .uebernachtungen_veraenderung_zum_vorjahreszeitraum_prozent,
+            })
+        })
+        .collect(),
+    })
+}
+
+/// The same month one year earlier.
+pub fn previous_year(period: YearMonth) -> YearMonth {
+    YearMonth {
+        year: period.year - 1,
+        month: period.month,
+    }
+}
+
+/// The stored counts of the month of the series by land and herkunft.
+pub async fn stored_counts(
+    tx: &mut PgConnection,
+    parser: SheetParser,
+    series_id: &str,
+    period: YearMonth,
+) -> Result<PreviousYearRows, anyhow::Error> {
+    Ok(stored_rows(tx, parser, series_id, period)
+        .await?
+        .into_iter()
+        .map(|x| {
+            (
+                x.key(),
+                PreviousYear {
+                    ankuenfte: x.ankuenfte,
+                    uebernachtungen: x.uebernachtungen,
+                },
+            )
+        })
+        .collect())
+}
+
+/// Recomputes the changes of the month of the series in the table of the parser
+/// against the same month one year earlier. A month that isn't stored is left alone.
+pub async fn recompute_month(
+    tx: &mut PgConnection,
+    parser: SheetParser,
+    series_id: &str,
+    period: YearMonth,
+) -> Result<RecomputeSummary, anyhow::Error> {
+    let mut summary = RecomputeSummary::default();
+    let rows = stored_rows(&mut *tx, parser, series_id, period).await?;
+    if rows.is_empty() {
+        return Ok(summary);
+    }
+    let previous = stored_counts(&mut *tx, parser, series_id, previous_year(period)).await?;
+
+    let jahr = period.year as i64;
+    let monat = translate_to_month(period.month as i32);
+    for row in &rows {
+        let [ankuenfte, uebernachtungen] = row.recompute(&previous);
+
+        match parser {
+            SheetParser::UebernachtungenNachHerkunftsland => {
+                sqlx::query_file!(
+                    "src/queries/update_uebernachtungen_nach_herkunftsland_veraenderung.sql",
+                    jahr,
+                    monat,
+                    row.herkunft,
+                    ankuenfte.value,
+                    ankuenfte.abgleich_code(),
+                    uebernachtungen.value,
+                    uebernachtungen.abgleich_code(),
+                    series_id
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+            SheetParser::UebernachtungenProLand => {
+                sqlx::query_file!(
+                    "src/queries/update_uebernachtungen_pro_land_veraenderung.sql",
+                    jahr,
+                    monat,
+                    row.land,
+                    row.herkunft,
+                    ankuenfte.value,
+                    ankuenfte.abgleich_code(),
+                    uebernachtungen.value,
+                    uebernachtungen.abgleich_code(),
+                    series_id
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        summary.count(&[ankuenfte, uebernachtungen]);
+    }
+    Ok(summary)
+}
+
+/// Recomputes the changes of every stored month, e.g. for months stored before the
+/// recomputed columns existed. No new data events are enqueued, the counts didn't
+/// change.
+pub async fn run(context: Context) -> Result<(), anyhow::Error> {
+    let task = configured_task::<GetSleepoverDataTask>(&context.settings)?;
+    for series in task.all_series() {
+        for mapping in &series.sheets {
+            let months = mapping
+                .parser
+                .fetched_months(&context.database_client, &series.id)
+                .await?;
+            let mut total = RecomputeSummary::default();
+            for period in months {
+                context.ensure_not_cancelled()?;
+                let mut tx = context.database_client.begin().await?;
+                total.add(recompute_month(&mut tx, mapping.parser, &series.id, period).await?);
+                tx.commit().await?;
+            }
+            info!(
+                "Recomputed {} rows of {} in {}, {} revised and {} filled change(s)",
+                total.rows,
+                series.name,
+                mapping.parser.table(),
+                total.revised,
+                total.filled
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::DestatisValue;
+
+    fn stored(
+        ankuenfte: Option<i64>,
+        ankuenfte_veraenderung: Option<f64>,
+        uebernachtungen: Option<i64>,
+        uebernachtungen_veraenderung: Option<f64>,
+    ) -> StoredRow {
+        StoredRow {
+            land: Some("Bayern ".to_string()),
+            herkunft: " Inland".to_string(),
+            ankuenfte,
+            ankuenfte_veraenderung,
+            uebernachtungen,
+            uebernachtungen_veraenderung,
+        }
+    }
+
+    fn previous(ankuenfte: Option<i64>, uebernachtungen: Option<i64>) -> PreviousYearRows {
+        PreviousYearRows::from([(
+            (Some("Bayern".to_string()), "Inland".to_string()),
+            PreviousYear {
+                ankuenfte,
+                uebernachtungen,
+            },
+        )])
+    }
+
+    fn assert_close(value: Option<f64>, expected: f64) {
+        let value = value.expect("a change");
+        assert!((value - expected).abs() < 1e-9, "{} != {}", value, expected);
+    }
+
+    #[test]
+    fn computes_the_change_in_percent() {
+        assert_close(change_percent(Some(110), Some(100)), 10.0);
+        assert_close(change_percent(Some(50), Some(100)), -50.0);
+        assert_close(change_percent(Some(0), Some(100)), -100.0);
+    }
+
+    #[test]
+    fn there_is_no_change_without_both_counts() {
+        assert_eq!(change_percent(None, Some(100)), None);
+        assert_eq!(change_percent(Some(100), None), None);
+        assert_eq!(change_percent(None, None), None);
+    }
+
+    #[test]
+    fn there_is_no_change_to_a_previous_year_of_zero() {
+        assert_eq!(change_percent(Some(100), Some(0)), None);
+        assert_eq!(change_percent(Some(0), Some(0)), None);
+        let recomputed = Recomputed::new(Some(100), Some(0), Some(12.5));
+        assert_eq!(recomputed.value, None);
+        assert_eq!(recomputed.abgleich, None);
+    }
+
+    #[test]
+    fn a_published_change_within_the_tolerance_matches() {
+        let recomputed = Recomputed::new(Some(1105), Some(1000), Some(10.5));
+        assert_eq!(recomputed.abgleich, Some(Abgleich::Match));
+        let recomputed = Recomputed::new(Some(1104), Some(1000), Some(10.45));
+        assert_eq!(recomputed.abgleich, Some(Abgleich::Match));
+        let recomputed = Recomputed::new(Some(1000), Some(1000), Some(-0.1));
+        assert_eq!(recomputed.abgleich, Some(Abgleich::Match));
+    }
+
+    #[test]
+    fn a_published_change_beyond_the_tolerance_is_revised() {
+        let recomputed = Recomputed::new(Some(1105), Some(1000), Some(10.3));
+        assert_eq!(recomputed.abgleich, Some(Abgleich::Revised));
+        assert_close(recomputed.value, 10.5);
+        let recomputed = Recomputed::new(Some(1105), Some(1000), Some(-10.5));
+        assert_eq!(recomputed.abgleich, Some(Abgleich::Revised));
+    }
+
+    #[test]
+    fn a_placeholder_instead_of_the_change_is_filled() {
+        // "." or "x" in the change column are stored without a value.
+        let published = DestatisValue::<f64>::parse(".").unwrap();
+        let recomputed = Recomputed::new(Some(1200), Some(1000), published.value);
+        assert_eq!(recomputed.abgleich, Some(Abgleich::Filled));
+        assert_close(recomputed.value, 20.0);
+    }
+
+    #[test]
+    fn placeholders_instead_of_the_counts() {
+        // "-" is a count of zero, "." has no count.
+        let zero = DestatisValue::<i64>::parse("-").unwrap().value;
+        let missing = DestatisValue::<i64>::parse(".").unwrap().value;
+        let recomputed = Recomputed::new(zero, Some(1000), Some(-100.0));
+        assert_eq!(recomputed.abgleich, Some(Abgleich::Match));
+        let recomputed = Recomputed::new(Some(1000), zero, None);
+        assert_eq!(recomputed.abgleich, None);
+        let recomputed = Recomputed::new(missing, Some(1000), Some(5.0));
+        assert_eq!(recomputed.value, None);
+        assert_eq!(recomputed.abgleich, None);
+    }
+
+    #[test]
+    fn recomputes_a_row_against_its_previous_year() {
+        let row = stored(Some(1100), Some(10.0), Some(3000), None);
+        let [ankuenfte, uebernachtungen] = row.recompute(&previous(Some(1000), Some(2500)));
+        assert_eq!(ankuenfte.abgleich, Some(Abgleich::Match));
+        assert_eq!(uebernachtungen.abgleich, Some(Abgleich::Filled));
+
+        let mut summary = RecomputeSummary::default();
+        summary.count(&[ankuenfte, uebernachtungen]);
+        let [ankuenfte, uebernachtungen] = stored(Some(1100), Some(12.0), Some(3000), Some(20.0))
+            .recompute(&previous(Some(1000), Some(2500)));
+        assert_eq!(ankuenfte.abgleich, Some(Abgleich::Revised));
+        assert_eq!(uebernachtungen.abgleich, Some(Abgleich::Match));
+        summary.count(&[ankuenfte, uebernachtungen]);
+        assert_eq!((summary.rows, summary.revised, summary.filled), (2, 1, 1));
+    }
+
+    #[test]
+    fn a_row_without_a_previous_year_is_left_without_change() {
+        let row = stored(Some(1100), Some(10.0), Some(3000), None);
+        let recomputed = row.recompute(&PreviousYearRows::new());
+        assert!(recomputed
+            .iter()
+            .all(|x| x.value.is_none() && x.abgleich.is_none()));
+
+        let recomputed = row.recompute(&previous(None, Some(2500)));
+        assert_eq!(recomputed[0].abgleich, None);
+        assert_eq!(recomputed[1].abgleich, Some(Abgleich::Filled));
+
+        let mut summary = RecomputeSummary::default();
+        summary.count(&row.recompute(&PreviousYearRows::new()));
+        assert_eq!((summary.rows, summary.revised, summary.filled), (1, 0, 0));
+    }
+
+    #[test]
+    fn the_previous_year_is_the_same_month() {
+        let period = YearMonth {
+            year: 2024,
+            month: 1,
+        };
+        assert_eq!(
+            previous_year(period),
+            YearMonth {
+                year: 2023,
+                month: 1
+            }
+        );
+    }
+}